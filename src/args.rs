@@ -1,75 +1,749 @@
+//! Command line of the `niri-scratchpad` binary. The same [`Action`]s are what clients send to the
+//! daemon.
+
 use clap::{Parser, Subcommand, ValueEnum};
+use niri_ipc::SizeChange;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::{fmt::Display, path::PathBuf, str::FromStr};
 
+/// A command, parsed from the command line and sent to the daemon as JSON.
 #[derive(Subcommand, Debug, Serialize, Deserialize)]
 pub enum Action {
-    #[command(about = "Target a window by app id or title.")]
+    /// Target a window by app id or title.
     Target {
+        /// The app id or title matching windows have.
         #[command(subcommand)]
         property: Property,
-        #[arg(
-            long,
-            help = "Spawn the application if no target is found",
-            name = "spawn command"
-        )]
-        spawn: Option<String>,
-        #[arg(
-            long,
-            help = "Effected windows will set themselves to floating (not on spawn)"
-        )]
+        /// What to launch when no window matches.
+        #[command(flatten)]
+        spawn: SpawnOptions,
+        /// Effected windows will set themselves to floating (not on spawn).
+        #[arg(long)]
         as_float: bool,
-        #[arg(long, help = "Effected windows will animate if floating")]
+        /// Effected windows will animate if floating.
+        #[arg(long)]
         animations: bool,
+        /// Restricts the toggle to one direction.
+        #[arg(long, value_enum, default_value_t, help = MODE_HELP)]
+        mode: Mode,
+        /// Focus a visible but unfocused window before anything is stashed.
+        #[arg(long, help = FOCUS_FIRST_HELP)]
+        focus_first: bool,
+        /// What to do with matching windows on another workspace.
+        #[arg(long, value_enum, default_value_t = Elsewhere::Stash, help = ELSEWHERE_HELP)]
+        elsewhere: Elsewhere,
+        /// Where summoned windows go.
+        #[arg(long = "to", default_value = "focused", help = DESTINATION_HELP, value_name = "DESTINATION")]
+        destination: Destination,
+        /// Where summoned tiled windows land on their workspace.
+        #[command(flatten)]
+        placement: PlacementOptions,
     },
+    /// Bind the focused window to a register, or toggle the register's window once it is bound.
+    #[command(visible_alias = "toggle")]
     Create {
-        register_number: i32,
+        /// Name of the register.
+        register_name: String,
+        /// Bind the first window matching `appid=<id>` or `title=<regex>` instead of the focused
+        /// one.
+        #[arg(long = "match", value_name = "PROPERTY=VALUE")]
+        matching: Option<Property>,
+        /// What to launch when the register is empty.
+        #[command(flatten)]
+        spawn: SpawnOptions,
+        /// Print a single property of the register.
         #[arg(short, long)]
         output: Option<Output>,
-        #[arg(
-            long,
-            help = "Initial register create will toggle floating on the window"
-        )]
+        /// How to print the register.
+        #[arg(long, help = FORMAT_HELP)]
+        format: Option<Format>,
+        /// Initial register create will toggle floating on the window.
+        #[arg(long)]
         as_float: bool,
-        #[arg(long, help = "Effected windows will animate if floating")]
+        /// Effected windows will animate if floating.
+        #[arg(long)]
         animations: bool,
+        /// Restricts the toggle to one direction.
+        #[arg(long, value_enum, default_value_t, help = MODE_HELP)]
+        mode: Mode,
+        /// Focus a visible but unfocused window before it is stashed.
+        #[arg(long, help = FOCUS_FIRST_HELP)]
+        focus_first: bool,
+        /// What to do with the window when it is on another workspace.
+        #[arg(long, value_enum, default_value_t = Elsewhere::Bring, help = ELSEWHERE_HELP)]
+        elsewhere: Elsewhere,
+        /// Where the summoned window goes.
+        #[arg(long = "to", default_value = "focused", help = DESTINATION_HELP, value_name = "DESTINATION")]
+        destination: Destination,
+        /// Where the summoned window lands on its workspace when it is tiled.
+        #[command(flatten)]
+        placement: PlacementOptions,
+    },
+    /// Summon a register's window unless it is on the focused workspace already.
+    Show {
+        /// Name of the register.
+        register_name: String,
+        /// Where the summoned window goes.
+        #[arg(long = "to", default_value = "focused", help = DESTINATION_HELP, value_name = "DESTINATION")]
+        destination: Destination,
+        /// Where the summoned window lands on its workspace when it is tiled.
+        #[command(flatten)]
+        placement: PlacementOptions,
+    },
+    /// Stash a register's window unless it is in the stash already.
+    Hide {
+        /// Name of the register.
+        register_name: String,
     },
+    /// Delete a register and summon its window onto the focused workspace.
     Delete {
-        register_number: i32,
+        /// Name of the register.
+        register_name: String,
+        /// Print a single property of the deleted register.
         #[arg(short, long)]
         output: Option<Output>,
+        /// How to print the deleted register.
+        #[arg(long, help = FORMAT_HELP)]
+        format: Option<Format>,
     },
+    /// Print a register.
     Get {
-        register_number: i32,
-        output: Output,
+        /// Name of the register.
+        register_name: String,
+        /// Print a single property of the register.
+        output: Option<Output>,
+        /// How to print the register.
+        #[arg(long, help = FORMAT_HELP)]
+        format: Option<Format>,
+    },
+    /// Stash the focused window into a register and put the register's window in its place.
+    Swap {
+        /// Name of the register.
+        register_name: String,
+    },
+    /// List all registers, one per line.
+    List {
+        /// How to print each register.
+        #[arg(long, help = FORMAT_HELP)]
+        format: Option<Format>,
     },
+    /// Update the registers from the windows niri reports.
     Sync,
-    Daemon,
+    /// Move every window out of the stash, back to where it was stashed from.
+    Rescue {
+        /// Keep the windows stashed and bind the ones no register holds to registers instead.
+        #[arg(long)]
+        adopt: bool,
+    },
+    /// Bind a window to a register, or every untracked window in the stash with --all.
+    Adopt {
+        /// Id of the window.
+        #[arg(required_unless_present = "all")]
+        window_id: Option<u64>,
+        /// Name of the register.
+        #[arg(required_unless_present = "all")]
+        register_name: Option<String>,
+        /// Adopt every stashed window no register or target holds into the next free registers.
+        #[arg(long, conflicts_with_all = ["window_id", "register_name"])]
+        all: bool,
+    },
+    /// List stashed windows no register or target holds, one per line.
+    Untracked,
+    /// List registers and known targets for a dmenu-style launcher and summon the chosen one.
+    Pick {
+        /// Summon the entry of a line `pick` printed.
+        #[arg(long, value_name = "LINE", conflicts_with_all = ["launcher", "print"])]
+        chosen: Option<String>,
+        /// Command to pipe the list through, e.g. 'fuzzel --dmenu', overrides the configured one.
+        #[arg(long, value_name = "COMMAND")]
+        launcher: Option<String>,
+        /// Print the list instead of passing it to the configured launcher.
+        #[arg(long, conflicts_with = "launcher")]
+        print: bool,
+    },
+    /// Run commands separated by `;` in one go, e.g. "hide 1; show 2; toggle term".
+    Batch {
+        /// Commands quoted like in a shell, a quoted or escaped `;` (`\;`) ends --spawn-argv
+        /// without ending the command. Nothing runs unless every command parses, but the commands
+        /// before one that fails have already run.
+        commands: String,
+    },
+    /// Print a line whenever the daemon reloads its config, or fails to.
+    Watch,
+    /// Run the register daemon.
+    Daemon {
+        /// Take over the registers of an already running daemon and replace it.
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Hands the registers over to a daemon that replaces this one.
+    #[command(hide = true)]
+    Handover,
+}
+
+/// One command of a `batch`, parsed like a command line without the binary name.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct BatchCommand {
+    #[command(subcommand)]
+    action: Action,
+}
+
+impl Action {
+    /// Splits `commands` at every `;` outside quotes and parses each command, its words are split
+    /// like a shell would. A quoted or escaped `;` ends `--spawn-argv` without ending the command.
+    /// Returns every command with its action, or the first error, so nothing runs unless all of
+    /// them parse.
+    pub fn parse_batch(commands: &str) -> Result<Vec<(String, Action)>, String> {
+        split_commands(commands)
+            .into_iter()
+            .map(str::trim)
+            .filter(|command| !command.is_empty())
+            .map(|command| {
+                let words =
+                    shell_words::split(command).map_err(|error| format!("`{command}`: {error}"))?;
+                let action = BatchCommand::try_parse_from(words)
+                    .map_err(|error| format!("`{command}`: {}", error.render()))?
+                    .action;
+                match action {
+                    Action::Batch { .. }
+                    | Action::Watch
+                    | Action::Daemon { .. }
+                    | Action::Handover => Err(format!("`{command}` can't be batched")),
+                    action => Ok((command.to_string(), action)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Splits `commands` at each `;` that is neither quoted nor escaped with a backslash.
+fn split_commands(commands: &str) -> Vec<&str> {
+    let mut pieces = vec![];
+    let (mut start, mut quote, mut escaped) = (0, None, false);
+    for (index, character) in commands.char_indices() {
+        match (quote, character) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(character),
+            (None, ';') => {
+                pieces.push(&commands[start..index]);
+                start = index + 1;
+            }
+            (None, _) => {}
+        }
+    }
+    pieces.push(&commands[start..]);
+    pieces
+}
+
+/// How to launch the application behind a target or an empty register. Either `spawn`, a shell
+/// command, or `spawn_argv`, a program and its arguments.
+#[derive(clap::Args, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpawnOptions {
+    /// Spawn the application with `sh -c` if nothing is found.
+    #[arg(long, value_name = "COMMAND", conflicts_with = "spawn_argv")]
+    pub spawn: Option<String>,
+    /// Spawn the application from a program and its arguments, ended by `;`.
+    #[arg(
+        long,
+        value_name = "ARG",
+        num_args = 1..,
+        value_terminator = ";",
+        allow_hyphen_values = true
+    )]
+    pub spawn_argv: Vec<String>,
+    /// Working directory of the spawned application.
+    #[arg(long)]
+    pub cwd: Option<PathBuf>,
+    /// Extra environment variable for the spawned application, repeatable.
+    #[arg(
+        long = "env",
+        value_name = "KEY=VAL",
+        value_parser = parse_env
+    )]
+    pub env: Vec<(String, String)>,
+}
+
+impl SpawnOptions {
+    /// `Some` when a command to spawn was given.
+    pub fn requested(self) -> Option<Self> {
+        (self.spawn.is_some() || !self.spawn_argv.is_empty()).then_some(self)
+    }
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VAL, got `{value}`"))
 }
 
-#[derive(Subcommand, Clone, Debug, Serialize, Deserialize)]
+/// Window property a static target matches on.
+#[derive(Subcommand, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Property {
+    /// Match windows by their exact app id.
     #[command(name = "appid")]
     AppId {
+        /// The app id.
         #[arg(name = "string")]
         value: String,
     },
+    /// Match windows by their exact title.
     #[command(name = "title")]
     Title {
+        /// The title.
         #[arg(name = "string")]
         value: String,
     },
+    /// Match windows whose title the regex finds a match in. Only given through
+    /// `create --match title=<regex>`.
+    #[command(skip)]
+    TitleRegex {
+        /// The regex.
+        value: TitleRegex,
+    },
+}
+
+impl FromStr for Property {
+    type Err = String;
+
+    /// Parses `appid=<id>` or `title=<regex>` as given to `create --match`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some(("appid", app_id)) => Ok(Property::AppId {
+                value: app_id.to_string(),
+            }),
+            Some(("title", title)) => Ok(Property::TitleRegex {
+                value: title.parse()?,
+            }),
+            _ => Err(format!(
+                "expected appid=<id> or title=<regex>, got `{value}`"
+            )),
+        }
+    }
+}
+
+impl Display for Property {
+    /// Formats the property the way `create --match` takes it.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::AppId { value } => write!(f, "appid={value}"),
+            Property::Title { value } => write!(f, "title={value}"),
+            Property::TitleRegex { value } => write!(f, "title={value}"),
+        }
+    }
+}
+
+/// A title regex, compiled once when it is parsed and sent to the daemon as its pattern.
+#[derive(Clone, Debug)]
+pub struct TitleRegex(Regex);
+
+impl TitleRegex {
+    /// Whether the regex matches anywhere in `title`.
+    pub fn is_match(&self, title: &str) -> bool {
+        self.0.is_match(title)
+    }
+}
+
+impl FromStr for TitleRegex {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Regex::new(value)
+            .map(TitleRegex)
+            .map_err(|error| error.to_string())
+    }
 }
 
+impl Display for TitleRegex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl PartialEq for TitleRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for TitleRegex {}
+
+impl Serialize for TitleRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TitleRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+const FOCUS_FIRST_HELP: &str =
+    "Focus a window that is visible but unfocused instead of stashing it";
+
+const MODE_HELP: &str = "Only ever show or hide the windows instead of toggling them";
+
+/// Which way a target or register may go when it is toggled. `show` and `hide` leave windows that
+/// are already where they should be alone, so they can be called without checking state first.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[value(rename_all = "lowercase")]
+pub enum Mode {
+    /// Summon the windows, or stash them when they are here already.
+    #[default]
+    Toggle,
+    /// Only ever summon the windows.
+    Show,
+    /// Only ever stash the windows.
+    Hide,
+}
+
+const ELSEWHERE_HELP: &str =
+    "What to do with windows on another workspace when none are here or stashed";
+
+/// What a toggle does with windows that are neither on the focused workspace nor in the stash.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[value(rename_all = "lowercase")]
+pub enum Elsewhere {
+    /// Move them to the focused workspace.
+    Bring,
+    /// Focus them where they are.
+    Goto,
+    /// Stash them, as if they were visible.
+    Stash,
+}
+
+const DESTINATION_HELP: &str = "Where summoned windows go: focused, workspace=<name|index>, output=<connector>, last or stash-output";
+
+/// Where a summoned window goes. Every destination is a workspace, the active one of an output
+/// unless a workspace is named.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Destination {
+    /// The focused workspace.
+    #[default]
+    Focused,
+    /// A workspace by name, or by index on the focused output.
+    Workspace(String),
+    /// An output by connector name, such as `DP-1`.
+    Output(String),
+    /// The output the window was on before it was stashed.
+    Last,
+    /// The output the stash workspace is on.
+    StashOutput,
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some(("workspace", workspace)) => Ok(Destination::Workspace(workspace.to_string())),
+            Some(("output", output)) => Ok(Destination::Output(output.to_string())),
+            None if value == "focused" => Ok(Destination::Focused),
+            None if value == "last" => Ok(Destination::Last),
+            None if value == "stash-output" => Ok(Destination::StashOutput),
+            _ => Err(format!(
+                "expected focused, workspace=<name|index>, output=<connector>, last or stash-output, got `{value}`"
+            )),
+        }
+    }
+}
+
+/// Where a summoned tiled window lands on its workspace, and how wide its column is. Floating
+/// windows are left alone.
+#[derive(clap::Args, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlacementOptions {
+    /// Where the window lands relative to the focused column.
+    #[arg(long, value_enum, default_value_t, help = PLACEMENT_HELP)]
+    pub placement: Placement,
+    /// Column width once summoned, a proportion such as 50% or logical pixels such as 800px.
+    #[arg(long, value_name = "WIDTH", value_parser = parse_width)]
+    pub width: Option<SizeChange>,
+}
+
+const PLACEMENT_HELP: &str = "Where a summoned tiled window lands relative to the focused column";
+
+/// Where a summoned tiled window goes, relative to the focused column of the destination.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[value(rename_all = "lowercase")]
+pub enum Placement {
+    /// Wherever niri puts a window moved to the workspace.
+    #[default]
+    Auto,
+    /// A new column right of the focused one.
+    Right,
+    /// A new column after the last one.
+    End,
+    /// A tab in the focused column.
+    Tab,
+    /// A tile stacked in the focused column.
+    Stack,
+    /// A new column in place of the focused one, which moves right, taking over its width.
+    Replace,
+}
+
+/// Parses a column width the way niri's `set-column-width` takes it, `<percent>%` or
+/// `<pixels>`, also with a `px` suffix. Changes relative to the current width are rejected.
+fn parse_width(value: &str) -> Result<SizeChange, String> {
+    let error = || format!("expected a width such as 50% or 800px, got `{value}`");
+    let pixels = value
+        .strip_suffix("px")
+        .filter(|pixels| !pixels.ends_with('%'));
+    match pixels.unwrap_or(value).parse::<SizeChange>() {
+        Ok(SizeChange::SetProportion(percent)) if percent > 0.0 && percent <= 100.0 => {
+            Ok(SizeChange::SetProportion(percent))
+        }
+        Ok(SizeChange::SetFixed(pixels)) if pixels > 0 => Ok(SizeChange::SetFixed(pixels)),
+        _ => Err(error()),
+    }
+}
+
+/// Single register property printed with `--output`.
 #[derive(ValueEnum, Clone, Debug, Serialize, Deserialize)]
 #[value(rename_all = "lowercase")]
 pub enum Output {
+    /// The window's title.
     Title,
+    /// The window's app id.
     AppId,
 }
 
+const FORMAT_HELP: &str =
+    "`json` for the whole register, or a template such as '{app_id}: {title}'";
+
+/// How register information is printed, either as JSON or through a template with `{field}`
+/// placeholders named after the JSON fields.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Format {
+    /// The whole register as JSON, see [`crate::format::RegisterInfo`].
+    Json,
+    /// A template such as `{app_id}: {title}`.
+    Template(String),
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "json" => Format::Json,
+            template => Format::Template(template.to_string()),
+        })
+    }
+}
+
+/// Command line of the `niri-scratchpad` binary.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// The command to run.
     #[command(subcommand)]
     pub action: Action,
+    /// Daemon socket, defaults to $NIRI_SCRATCHPAD_SOCKET or one named after the niri socket.
+    #[arg(long, global = true)]
+    pub socket: Option<PathBuf>,
+    /// Niri socket, defaults to $NIRI_SOCKET.
+    #[arg(long, global = true)]
+    pub niri_socket: Option<PathBuf>,
+    /// Config file, defaults to $XDG_CONFIG_HOME/niri-scratchpad/config.toml.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Result<Action, clap::Error> {
+        Args::try_parse_from(
+            ["niri-scratchpad"]
+                .into_iter()
+                .chain(command.split_whitespace()),
+        )
+        .map(|args| args.action)
+    }
+
+    fn spawn_options(action: &Action) -> &SpawnOptions {
+        match action {
+            Action::Create { spawn, .. } | Action::Target { spawn, .. } => spawn,
+            _ => panic!("not a command with spawn options"),
+        }
+    }
+
+    #[test]
+    fn batch_splits_at_unquoted_semicolons() {
+        let actions = Action::parse_batch("hide 1; show 2 ;; toggle term").unwrap();
+        let commands: Vec<_> = actions
+            .iter()
+            .map(|(command, _)| command.as_str())
+            .collect();
+        assert_eq!(commands, ["hide 1", "show 2", "toggle term"]);
+    }
+
+    #[test]
+    fn batch_keeps_quoted_arguments_together() {
+        let actions =
+            Action::parse_batch(r#"create mail --spawn 'thunderbird --new-window; true'; hide 1"#)
+                .unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(
+            spawn_options(&actions[0].1).spawn.as_deref(),
+            Some("thunderbird --new-window; true")
+        );
+    }
+
+    #[test]
+    fn batch_ends_spawn_argv_at_an_escaped_semicolon() {
+        let actions =
+            Action::parse_batch(r"create term --spawn-argv foot -e htop \; --as-float; hide 1")
+                .unwrap();
+        assert_eq!(actions.len(), 2);
+        let Action::Create { as_float, .. } = &actions[0].1 else {
+            panic!("not a create");
+        };
+        assert!(as_float);
+        assert_eq!(
+            spawn_options(&actions[0].1).spawn_argv,
+            ["foot", "-e", "htop"]
+        );
+    }
+
+    #[test]
+    fn batch_ends_spawn_argv_with_the_command() {
+        let actions = Action::parse_batch("create term --spawn-argv foot -e htop; hide 1").unwrap();
+        assert_eq!(
+            spawn_options(&actions[0].1).spawn_argv,
+            ["foot", "-e", "htop"]
+        );
+        assert!(matches!(actions[1].1, Action::Hide { .. }));
+    }
+
+    #[test]
+    fn batch_rejects_everything_when_one_command_is_invalid() {
+        assert!(Action::parse_batch("hide 1; frobnicate").is_err());
+        assert!(Action::parse_batch("hide 1; show 'unterminated").is_err());
+        assert!(Action::parse_batch("hide 1; daemon").is_err());
+    }
+
+    #[test]
+    fn width_takes_proportions_and_pixels() {
+        assert_eq!(parse_width("50%"), Ok(SizeChange::SetProportion(50.0)));
+        assert_eq!(parse_width("800px"), Ok(SizeChange::SetFixed(800)));
+        assert_eq!(parse_width("800"), Ok(SizeChange::SetFixed(800)));
+    }
+
+    #[test]
+    fn width_rejects_relative_and_out_of_range_values() {
+        for value in ["+10%", "-100", "0", "0%", "150%", "50%px", "wide", ""] {
+            assert!(parse_width(value).is_err(), "{value} was accepted");
+        }
+    }
+
+    #[test]
+    fn match_parses_app_ids_and_title_regexes() {
+        assert_eq!(
+            "appid=org.gnome.Nautilus".parse::<Property>(),
+            Ok(Property::AppId {
+                value: "org.gnome.Nautilus".to_string()
+            })
+        );
+        let Ok(Property::TitleRegex { value }) = "title=^Mail - .*=x$".parse::<Property>() else {
+            panic!("not a title regex");
+        };
+        assert!(value.is_match("Mail - Inbox=x"));
+        assert!(!value.is_match("Re: Mail - Inbox=x"));
+    }
+
+    #[test]
+    fn match_rejects_unknown_keys_and_invalid_regexes() {
+        for value in ["firefox", "class=firefox", "title=(unclosed", "=firefox"] {
+            assert!(value.parse::<Property>().is_err(), "{value} was accepted");
+        }
+    }
+
+    #[test]
+    fn match_displays_as_it_is_parsed() {
+        for value in ["appid=foot", "title=^htop$"] {
+            assert_eq!(value.parse::<Property>().unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn title_regex_round_trips_through_its_pattern() {
+        let property: Property = "title=^Mail".parse().unwrap();
+        let json = serde_json::to_string(&property).unwrap();
+        assert_eq!(serde_json::from_str::<Property>(&json).unwrap(), property);
+    }
+
+    fn elsewhere(command: &str) -> Elsewhere {
+        match parse(command).unwrap() {
+            Action::Create { elsewhere, .. } | Action::Target { elsewhere, .. } => elsewhere,
+            _ => panic!("not a command with --elsewhere"),
+        }
+    }
+
+    #[test]
+    fn elsewhere_defaults_to_stash_for_targets_and_bring_for_registers() {
+        assert_eq!(elsewhere("target appid foot"), Elsewhere::Stash);
+        assert_eq!(elsewhere("create term"), Elsewhere::Bring);
+    }
+
+    #[test]
+    fn elsewhere_takes_every_value() {
+        assert_eq!(elsewhere("create term --elsewhere stash"), Elsewhere::Stash);
+        assert_eq!(
+            elsewhere("target --elsewhere goto appid foot"),
+            Elsewhere::Goto
+        );
+        assert_eq!(
+            elsewhere("target --elsewhere bring title htop"),
+            Elsewhere::Bring
+        );
+        assert_eq!(Elsewhere::from_str("GOTO", true), Ok(Elsewhere::Goto));
+        assert!(parse("create term --elsewhere hide").is_err());
+    }
+
+    #[test]
+    fn destination_parses_every_form() {
+        let cases = [
+            ("focused", Destination::Focused),
+            ("last", Destination::Last),
+            ("stash-output", Destination::StashOutput),
+            ("workspace=mail", Destination::Workspace("mail".to_string())),
+            ("workspace=2", Destination::Workspace("2".to_string())),
+            ("output=DP-1", Destination::Output("DP-1".to_string())),
+        ];
+        for (value, destination) in cases {
+            assert_eq!(value.parse(), Ok(destination), "{value}");
+        }
+    }
+
+    #[test]
+    fn destination_rejects_unknown_forms() {
+        for value in ["", "stash", "monitor=DP-1", "last=1", "Focused"] {
+            assert!(
+                value.parse::<Destination>().is_err(),
+                "{value} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn destination_defaults_to_the_focused_workspace() {
+        let Action::Show { destination, .. } = parse("show term").unwrap() else {
+            panic!("not a show");
+        };
+        assert_eq!(destination, Destination::Focused);
+    }
 }
@@ -0,0 +1,158 @@
+//! Client side of the daemon protocol: every request is one JSON encoded [`Action`] per line,
+//! answered by one JSON encoded [`Response`] line. A connection can carry any number of requests.
+
+use crate::args::Action;
+use crate::sockets::SocketPaths;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Result, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+/// The text a command prints, or why it failed.
+pub type Reply = std::result::Result<String, String>;
+
+/// The daemon's answer to one request: the [`Reply`], and a notice for the user that is not part
+/// of the command's output. Without a notice it is encoded just like the bare [`Reply`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    /// The command's output, or why it failed.
+    #[serde(flatten)]
+    pub reply: Reply,
+    /// Something the user should know that is not the command's output, printed to stderr.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+}
+
+impl From<Reply> for Response {
+    fn from(reply: Reply) -> Self {
+        Response {
+            reply,
+            notice: None,
+        }
+    }
+}
+
+/// Handle to the register daemon behind [`SocketPaths::daemon`].
+#[derive(Debug, Clone)]
+pub struct Client {
+    paths: SocketPaths,
+}
+
+impl Client {
+    /// A client for the daemon at `paths.daemon`, nothing is connected yet.
+    pub fn new(paths: SocketPaths) -> Self {
+        Client { paths }
+    }
+
+    /// The sockets this client was created with.
+    pub fn paths(&self) -> &SocketPaths {
+        &self.paths
+    }
+
+    /// Whether a daemon is listening on the configured socket.
+    pub fn is_daemon_running(&self) -> bool {
+        UnixStream::connect(&self.paths.daemon).is_ok()
+    }
+
+    /// Starts `exe daemon` for the configured sockets unless a daemon already answers, then waits
+    /// for it to come up. `exe` is the `niri-scratchpad` binary, `config` is passed on as
+    /// `--config`.
+    pub fn ensure_daemon(&self, exe: &Path, config: Option<&Path>) -> Result<()> {
+        if self.is_daemon_running() {
+            return Ok(());
+        }
+
+        let mut command = Command::new(exe);
+        command
+            .arg("daemon")
+            .arg("--socket")
+            .arg(&self.paths.daemon)
+            .arg("--niri-socket")
+            .arg(&self.paths.niri);
+        if let Some(config) = config {
+            command.arg("--config").arg(config);
+        }
+        // The daemon outlives this process, holding on to its stdout would block `$(...)` callers.
+        command.stdin(Stdio::null()).stdout(Stdio::null()).spawn()?;
+
+        for _ in 0..40 {
+            sleep(Duration::from_millis(50));
+            if self.is_daemon_running() {
+                return Ok(());
+            }
+        }
+
+        Err(Error::new(
+            ErrorKind::NotConnected,
+            "Failed to start daemon",
+        ))
+    }
+
+    /// Sends one action to the daemon and returns its output. Errors reported by the daemon
+    /// come back as [`ErrorKind::Other`], notices it sends along are printed to stderr.
+    pub fn request(&self, action: &Action) -> Result<String> {
+        self.connect()?.request(action)
+    }
+
+    /// Opens a connection for several requests in a row.
+    pub fn connect(&self) -> Result<Connection> {
+        Connection::new(UnixStream::connect(&self.paths.daemon)?)
+    }
+
+    /// Asks the daemon for config reloads and passes each [`Reply`] to `on_reply` until the
+    /// daemon goes away.
+    pub fn watch(&self, mut on_reply: impl FnMut(Reply)) -> Result<()> {
+        let mut stream = UnixStream::connect(&self.paths.daemon)?;
+        writeln!(stream, "{}", serde_json::to_string(&Action::Watch)?)?;
+        for line in BufReader::new(&stream).lines() {
+            on_reply(serde_json::from_str(&line?)?);
+        }
+        Ok(())
+    }
+
+    /// Sends `action` over an already connected daemon stream and waits for the reply.
+    pub fn exchange(stream: UnixStream, action: &Action) -> Result<String> {
+        Connection::new(stream)?.request(action)
+    }
+}
+
+/// An open connection to the daemon, requests are answered in the order they are sent.
+#[derive(Debug)]
+pub struct Connection {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Connection {
+    /// Wraps a stream connected to the daemon.
+    pub fn new(stream: UnixStream) -> Result<Self> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        })
+    }
+
+    /// Sends one action and waits for its response, see [`Client::request`].
+    pub fn request(&mut self, action: &Action) -> Result<String> {
+        writeln!(self.stream, "{}", serde_json::to_string(action)?)?;
+
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        if line.is_empty() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "daemon closed the connection without replying",
+            ));
+        }
+        let response: Response = serde_json::from_str(&line)?;
+        if let Some(notice) = response.notice {
+            eprintln!("{notice}");
+        }
+        response.reply.map_err(Error::other)
+    }
+}
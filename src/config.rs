@@ -0,0 +1,300 @@
+//! Optional configuration file, `$XDG_CONFIG_HOME/niri-scratchpad/config.toml` unless given
+//! with `--config`.
+
+use crate::args::Property;
+use crate::hooks::HookEvent;
+use crate::target_action::match_window_by_property;
+use niri_ipc::Window;
+use serde::Deserialize;
+use std::{
+    env::var_os,
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+/// The whole config file, every table is optional.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The `[[rule]]` tables.
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+    /// The `[[target]]` tables.
+    #[serde(rename = "target")]
+    pub targets: Vec<Target>,
+    /// Bind windows moved into the stash by other means than this tool to registers, see
+    /// [`crate::register_action::adopt_window`].
+    pub auto_adopt: bool,
+    /// The `[stash]` table.
+    pub stash: Stash,
+    /// The `[hooks]` table.
+    pub hooks: Hooks,
+    /// The `[pick]` table.
+    pub pick: Pick,
+}
+
+/// The `[stash]` table, which workspace windows are stashed on.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stash {
+    /// Name of the stash workspace.
+    pub workspace: String,
+    /// Output to create the stash workspace on, the focused one when unset.
+    pub output: Option<String>,
+    /// Name an empty workspace after the stash when there is no such workspace yet.
+    pub create: bool,
+}
+
+impl Default for Stash {
+    fn default() -> Self {
+        Stash {
+            workspace: "stash".to_string(),
+            output: None,
+            create: true,
+        }
+    }
+}
+
+/// The `[hooks]` table, a shell command per event. Each runs through `sh -c` with the event in
+/// `$NIRI_SCRATCHPAD_EVENT` and the register or target it happened to as JSON on stdin.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs when a register is bound to a window.
+    pub create: Option<String>,
+    /// Runs when a register is deleted with `delete`.
+    pub delete: Option<String>,
+    /// Runs when a scratchpad's window is summoned.
+    pub summon: Option<String>,
+    /// Runs when a scratchpad's window is stashed.
+    pub stash: Option<String>,
+    /// Runs when the window of a register closes.
+    pub drop: Option<String>,
+    /// Runs when a program is launched for a static target or a register.
+    pub spawn: Option<String>,
+}
+
+impl Hooks {
+    /// The command configured for `event`.
+    pub(crate) fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Create => &self.create,
+            HookEvent::Delete => &self.delete,
+            HookEvent::Summon => &self.summon,
+            HookEvent::Stash => &self.stash,
+            HookEvent::Drop => &self.drop,
+            HookEvent::Spawn => &self.spawn,
+        }
+        .as_deref()
+    }
+}
+
+/// The `[pick]` table.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pick {
+    /// dmenu-style command `pick` pipes its list through, such as `fuzzel --dmenu`.
+    pub launcher: Option<String>,
+}
+
+/// Binds the first window matching `app_id` or `title` to `register` when it opens.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Recorded on the registers this rule creates, defaults to the register name.
+    pub name: Option<String>,
+    /// The register matching windows are bound to, unless it holds an open window already.
+    pub register: String,
+    /// Exact app id to match, exclusive with `title`.
+    pub app_id: Option<String>,
+    /// Exact title to match, exclusive with `app_id`.
+    pub title: Option<String>,
+    /// Make the window floating once it is bound.
+    #[serde(default)]
+    pub float: bool,
+    /// Stash the window once it is bound.
+    #[serde(default)]
+    pub stash: bool,
+}
+
+/// A static target known before it is ever toggled, its stashed windows are not up for adoption.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// Exact app id to match, exclusive with `title`.
+    pub app_id: Option<String>,
+    /// Exact title to match, exclusive with `app_id`.
+    pub title: Option<String>,
+}
+
+impl Config {
+    /// Reads `path`, or the default location when `None`. Only a missing default file yields the
+    /// default configuration, an explicitly given file has to exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path)?;
+        let config: Config = toml::from_str(&contents).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), error.message()),
+            )
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The file [`Config::load`] reads for `path`, whether it exists or not.
+    pub fn path(path: Option<&Path>) -> Option<PathBuf> {
+        path.map(Path::to_path_buf).or_else(default_path)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for rule in &self.rules {
+            if rule.app_id.is_some() == rule.title.is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "rule `{}` needs exactly one of `app_id` or `title`",
+                        rule.name()
+                    ),
+                ));
+            }
+        }
+        if self
+            .targets
+            .iter()
+            .any(|target| target.app_id.is_some() == target.title.is_some())
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "every target needs exactly one of `app_id` or `title`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    /// The rule's name, or its register's when it has none.
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.register)
+    }
+
+    /// The app id or title the rule matches.
+    pub fn property(&self) -> Property {
+        property(&self.app_id, &self.title)
+    }
+
+    /// Whether `window` has the rule's exact app id or title.
+    pub fn matches(&self, window: &Window) -> bool {
+        match_window_by_property(window, &self.property())
+    }
+}
+
+impl Target {
+    /// The property `target appid` or `target title` would be given for this target.
+    pub fn property(&self) -> Property {
+        property(&self.app_id, &self.title)
+    }
+}
+
+/// An exact match on `app_id`, or else on `title`.
+fn property(app_id: &Option<String>, title: &Option<String>) -> Property {
+    match (app_id, title) {
+        (Some(app_id), _) => Property::AppId {
+            value: app_id.clone(),
+        },
+        (None, title) => Property::Title {
+            value: title.clone().unwrap_or_default(),
+        },
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("niri-scratchpad").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::window;
+
+    fn parse(contents: &str) -> Result<Config> {
+        let config: Config = toml::from_str(contents).map_err(Error::other)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn rules_and_targets_take_an_app_id_or_a_title() {
+        let config = parse(
+            r#"
+            [[rule]]
+            register = "term"
+            app_id = "foot"
+            float = true
+
+            [[rule]]
+            name = "mail"
+            register = "m"
+            title = "Inbox"
+
+            [[target]]
+            app_id = "firefox"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rules[0].name(), "term");
+        assert!(config.rules[0].float && !config.rules[0].stash);
+        assert_eq!(config.rules[1].name(), "mail");
+        assert_eq!(config.targets[0].property().to_string(), "appid=firefox");
+    }
+
+    #[test]
+    fn rules_need_exactly_one_of_app_id_or_title() {
+        let both = parse("[[rule]]\nregister = \"term\"\napp_id = \"foot\"\ntitle = \"htop\"");
+        assert!(both.unwrap_err().to_string().contains("rule `term`"));
+        assert!(parse("[[rule]]\nname = \"x\"\nregister = \"term\"").is_err());
+    }
+
+    #[test]
+    fn targets_need_exactly_one_of_app_id_or_title() {
+        assert!(parse("[[target]]").is_err());
+        assert!(parse("[[target]]\napp_id = \"foot\"\ntitle = \"htop\"").is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(parse("[[rule]]\nregister = \"term\"\nappid = \"foot\"").is_err());
+        assert!(parse("[stash]\nname = \"scratch\"").is_err());
+    }
+
+    #[test]
+    fn rules_match_app_ids_and_titles_exactly() {
+        let config = parse(
+            "[[rule]]\nregister = \"term\"\napp_id = \"foot\"\n\n\
+             [[rule]]\nregister = \"mail\"\ntitle = \"Inbox\"",
+        )
+        .unwrap();
+        let (term, mail) = (&config.rules[0], &config.rules[1]);
+        assert!(term.matches(&window(1, "foot", "htop", None)));
+        assert!(!term.matches(&window(1, "footclient", "htop", None)));
+        assert!(mail.matches(&window(1, "thunderbird", "Inbox", None)));
+        assert!(!mail.matches(&window(1, "thunderbird", "Inbox - Thunderbird", None)));
+    }
+
+    #[test]
+    fn an_explicit_config_file_has_to_exist() {
+        assert!(Config::load(Some(Path::new("/nonexistent/niri-scratchpad.toml"))).is_err());
+    }
+}
@@ -1,47 +1,168 @@
+use crate::client::{Client, Reply, Response};
+use crate::config::{Config, Hooks};
+use crate::events::spawn_event_listener;
+use crate::format::{RegisterInfo, render_all};
+use crate::hooks::{HookContext, HookEvent, run_hook, run_register_hook};
+use crate::pick::{self, PickEntry};
 use crate::register_action::{RegisterInformation, RegisterStatus};
+use crate::reload::{SharedConfig, spawn_config_watcher};
+use crate::snapshot::Snapshot;
+use crate::sockets::{SocketPaths, current_uid, peer_credentials};
 use crate::state::{Register, State};
-use crate::target_action::handle_target;
-use crate::utils::{set_floating, set_tiling};
+use crate::systemd::{self, Activation};
+use crate::target_action::{
+    self, REGISTER_ENV, TargetOutcome, ToggleOptions, get_windows_by_property, handle_target,
+};
+use crate::utils::{ensure_stash_workspace, set_floating, set_tiling};
 use crate::{
-    args::{Action, Output},
+    args::{Action, Destination, Elsewhere, Format, Mode, Output, PlacementOptions, Property},
     register_action,
 };
 use niri_ipc::socket::Socket;
-use niri_ipc::{Request as NiriRequest, Response as NiriResponse};
+use niri_ipc::{Action::FocusWindow, Request as NiriRequest, Window};
 use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
 use std::{
-    env::var,
-    io::{BufRead, BufReader, Result, Write},
-    os::unix::net::UnixListener,
-    path::PathBuf,
+    io::{BufRead, BufReader, Error, ErrorKind, Result, Write},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
 };
 
+const DEFAULT_LIST_TEMPLATE: &str = "{name}\t{app_id}\t{title}";
+
 struct RegisterWithStatus {
     status: RegisterStatus,
     register: Register,
 }
 
-struct FocusedWindowContext {
-    window_id: u64,
-    title: Option<String>,
-    app_id: Option<String>,
-    current_workspace_id: u64,
+/// The daemon's one connection to niri, shared by every client and opened again when niri stops
+/// answering on it.
+struct Niri {
+    paths: SocketPaths,
+    socket: Option<Socket>,
+}
+
+impl Niri {
+    /// Returns the connection along with a fresh [`Snapshot`]. A connection that fails is
+    /// replaced once, niri may have restarted since the last command.
+    fn snapshot(&mut self, stash_name: &str) -> Result<(&mut Socket, Snapshot)> {
+        let mut socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => self.paths.connect_niri()?,
+        };
+        let snapshot = match Snapshot::take(&mut socket, stash_name) {
+            Ok(snapshot) => snapshot,
+            Err(_) => {
+                socket = self.paths.connect_niri()?;
+                Snapshot::take(&mut socket, stash_name)?
+            }
+        };
+        Ok((self.socket.insert(socket), snapshot))
+    }
+}
+
+/// How a client that takes over tells the accept loop to stop.
+#[derive(Clone)]
+struct Handover {
+    requested: Arc<AtomicBool>,
+    /// Address of the daemon socket, connected to once to wake the accept loop.
+    wake: Option<PathBuf>,
+    /// Sent once the registers are handed over, the accept loop returns then.
+    done: Sender<()>,
 }
 
-pub fn run_daemon() -> Result<()> {
-    let socket_path = get_socket_path()?;
-    if socket_path.exists() {
-        std::fs::remove_file(&socket_path)?;
+/// Serves register commands on [`SocketPaths::daemon`], or on the socket systemd passed in
+/// `activation`, until another daemon takes over. `config_path` is the `--config` to load and
+/// reload. Fails with [`ErrorKind::AddrInUse`] when a daemon already answers and `replace` isn't
+/// set.
+pub fn run_daemon(
+    paths: SocketPaths,
+    config_path: Option<PathBuf>,
+    replace: bool,
+    activation: Activation,
+) -> Result<()> {
+    let Activation {
+        listener: inherited_listener,
+        notify_socket,
+    } = activation;
+    if replace && inherited_listener.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "`--replace` cannot take over a socket systemd passed in, restart the service instead",
+        ));
     }
-    let listener = UnixListener::bind(&socket_path)?;
+    let config = SharedConfig::new(Config::load(config_path.as_deref())?, config_path);
+    let socket_path = &paths.daemon;
     let mut state = State::new();
+    let listener = match inherited_listener {
+        Some(listener) => listener,
+        None => {
+            // Held from the probe to the bind, so daemons starting together over a stale socket
+            // cannot remove each other's fresh one
+            let _lock = paths.lock_daemon()?;
+            if socket_path.exists() {
+                // Only a socket nobody answers on is stale, a live one belongs to another daemon.
+                if let Ok(stream) = UnixStream::connect(socket_path) {
+                    if !replace {
+                        return Err(Error::new(
+                            ErrorKind::AddrInUse,
+                            format!(
+                                "A daemon is already running on {}, use `daemon --replace` to take over",
+                                socket_path.display()
+                            ),
+                        ));
+                    }
+                    state = take_over(stream)?;
+                }
+                std::fs::remove_file(socket_path)?;
+            }
+            paths.bind_daemon()?
+        }
+    };
+    let state = Arc::new(Mutex::new(state));
+    let niri = Arc::new(Mutex::new(Niri {
+        paths: paths.clone(),
+        socket: None,
+    }));
+    spawn_event_listener(&paths, config.clone(), Arc::clone(&state))?;
+    spawn_config_watcher(config.clone())?;
+    if let Some(notify_socket) = notify_socket
+        && let Err(error) = systemd::notify_ready(&notify_socket)
+    {
+        eprintln!("Failed to notify systemd: {error}");
+    }
 
+    let uid = current_uid();
+    let (done, handed_over) = mpsc::channel();
+    let handover = Handover {
+        requested: Arc::new(AtomicBool::new(false)),
+        wake: listener
+            .local_addr()?
+            .as_pathname()
+            .map(|path| path.to_path_buf()),
+        done,
+    };
     for stream in listener.incoming() {
+        if handover.requested.load(Ordering::SeqCst) {
+            // Returning before the registers went out would end the process under that client
+            let _ = handed_over.recv();
+            break;
+        }
         match stream {
+            Ok(stream) if !is_same_user(&stream, uid) => {}
             Ok(stream) => {
-                if let Err(e) = handle_client(stream, &mut state) {
-                    eprintln!("Error handling client: {}", e);
-                }
+                let (state, niri, config) = (Arc::clone(&state), Arc::clone(&niri), config.clone());
+                let handover = handover.clone();
+                // Clients may keep their connection open, so each one is served on its own thread
+                thread::spawn(move || {
+                    if let Err(e) = handle_client(stream, &state, &niri, &config, &handover) {
+                        eprintln!("Error handling client: {}", e);
+                    }
+                });
             }
             Err(e) => eprintln!("Connection error: {}", e),
         }
@@ -50,149 +171,637 @@ pub fn run_daemon() -> Result<()> {
     Ok(())
 }
 
-fn handle_client(stream: UnixStream, state: &mut State) -> Result<()> {
-    let mut reader = BufReader::new(&stream);
-    let mut line = String::new();
-    reader.read_line(&mut line)?;
+/// Whether the peer on `stream` runs as `uid`. Anyone else could make the daemon spawn commands,
+/// so other users are turned away and logged.
+fn is_same_user(stream: &UnixStream, uid: u32) -> bool {
+    match peer_credentials(stream) {
+        Ok(peer) if peer.uid == uid => true,
+        Ok(peer) => {
+            eprintln!(
+                "Rejected connection from pid {} of uid {}, the daemon runs as uid {uid}",
+                peer.pid, peer.uid
+            );
+            false
+        }
+        Err(error) => {
+            eprintln!("Rejected connection without credentials: {error}");
+            false
+        }
+    }
+}
+
+/// Answers every command line the client sends with one reply line, in order, until it hangs up.
+/// A probe from another daemon checking whether this one is alive sends nothing.
+fn handle_client(
+    stream: UnixStream,
+    state: &Mutex<State>,
+    niri: &Mutex<Niri>,
+    shared_config: &SharedConfig,
+    handover: &Handover,
+) -> Result<()> {
+    for line in BufReader::new(&stream).lines() {
+        // A client of another version may send what this daemon doesn't know
+        let action: Action = match serde_json::from_str(&line?) {
+            Ok(action) => action,
+            Err(error) => {
+                write_response(&stream, Err(format!("malformed request: {error}")).into())?;
+                continue;
+            }
+        };
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        match action {
+            Action::Handover => {
+                // The socket stays ours until the new daemon has read the registers, so this
+                // connection reaches our own accept loop and lets it see the handover
+                handover.requested.store(true, Ordering::SeqCst);
+                if let Some(wake) = &handover.wake {
+                    let _ = UnixStream::connect(wake);
+                }
+                let reply = serde_json::to_string(&*state)
+                    .map_err(Error::from)
+                    .and_then(|registers| write_response(&stream, Ok(registers).into()));
+                let _ = handover.done.send(());
+                return reply;
+            }
+            Action::Watch => {
+                write_response(
+                    &stream,
+                    Ok("watching for config reloads".to_string()).into(),
+                )?;
+                shared_config.add_watcher(stream.try_clone()?)?;
+                return Ok(());
+            }
+            action => {
+                let mut niri = niri.lock().unwrap_or_else(PoisonError::into_inner);
+                let response = handle_action(action, &mut state, &shared_config.get(), &mut niri);
+                write_response(&stream, response)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_action(action: Action, state: &mut State, config: &Config, niri: &mut Niri) -> Response {
+    // Every command of a batch sees what the ones before it did
+    if let Action::Batch { commands } = action {
+        let actions = match Action::parse_batch(&commands) {
+            Ok(actions) => actions,
+            Err(message) => return Err(message).into(),
+        };
+        let (mut outputs, mut notices) = (vec![], vec![]);
+        for (command, action) in actions {
+            let response = handle_action(action, state, config, niri);
+            notices.extend(response.notice);
+            match response.reply {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => outputs.push(output),
+                Err(message) => {
+                    return Response {
+                        reply: Err(format!("`{command}` failed: {message}")),
+                        notice: join_notices(notices),
+                    };
+                }
+            }
+        }
+        return Response {
+            reply: Ok(outputs.join("\n")),
+            notice: join_notices(notices),
+        };
+    }
+    let (socket, mut snapshot) = match niri.snapshot(&config.stash.workspace) {
+        Ok(connection) => connection,
+        Err(error) => return Err(format!("niri did not answer: {error}")).into(),
+    };
+    // Read only commands leave the workspaces alone
+    let notice = if moves_windows(&action) {
+        ensure_stash_workspace(socket, &mut snapshot, &config.stash).1
+    } else {
+        None
+    };
+    let reply = run_action(action, state, config, socket, snapshot);
+    Response { reply, notice }
+}
 
-    let action: Action = serde_json::from_str(&line)?;
-    let mut socket = Socket::connect()?;
+/// Whether `action` may move windows to or from the stash workspace.
+fn moves_windows(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::Create { .. }
+            | Action::Show { .. }
+            | Action::Hide { .. }
+            | Action::Delete { .. }
+            | Action::Swap { .. }
+            | Action::Target { .. }
+            | Action::Pick {
+                chosen: Some(_),
+                ..
+            }
+    )
+}
 
+fn join_notices(notices: Vec<String>) -> Option<String> {
+    (!notices.is_empty()).then(|| notices.join("\n"))
+}
+
+fn run_action(
+    action: Action,
+    state: &mut State,
+    config: &Config,
+    socket: &mut Socket,
+    snapshot: Snapshot,
+) -> Reply {
     let response = match action {
-        Action::Daemon => return Ok(()),
+        Action::Daemon { .. } | Action::Handover | Action::Watch | Action::Batch { .. } => {
+            String::new()
+        }
         Action::Create {
-            register_number,
+            register_name,
+            matching,
+            spawn,
             output,
+            format,
             as_float,
             animations,
+            mode,
+            focus_first,
+            elsewhere,
+            destination,
+            placement,
         } => {
-            let (
-                Ok(NiriResponse::FocusedWindow(focused_window)),
-                Ok(NiriResponse::Workspaces(workspaces)),
-            ) = (
-                socket.send(NiriRequest::FocusedWindow)?,
-                socket.send(NiriRequest::Workspaces)?,
-            )
-            else {
-                return Ok(());
-            };
-            let Some(current_workspace) = workspaces.iter().find(|workspace| workspace.is_focused)
-            else {
-                return write_response(&stream, "");
-            };
+            let spawn = spawn.requested();
+            if let Some(property) = &matching {
+                match bind_matching_window(
+                    socket,
+                    &snapshot,
+                    state,
+                    &register_name,
+                    property,
+                    as_float,
+                ) {
+                    Ok(()) => {
+                        run_register_hook(&config.hooks, HookEvent::Create, state, &register_name);
+                        let snapshot = refresh(socket, snapshot, format.is_some());
+                        let info = describe_register(&snapshot, state, &register_name);
+                        return Ok(render_info(info, format, output));
+                    }
+                    // Nothing matches yet, so the spawned program's window will
+                    Err(_) if spawn.is_some() => {}
+                    Err(message) => return Err(message),
+                }
+            }
+            if let Some(spawn) = spawn
+                && (matching.is_some()
+                    || register_check(&snapshot, state, &register_name).is_none_or(|register| {
+                        matches!(register.status, RegisterStatus::WindowDropped)
+                    }))
+            {
+                // Pressing the key again while the window is still starting must not spawn twice
+                if state.is_spawn_pending(&register_name) {
+                    return Ok(String::new());
+                }
+                let scratchpad_env = (REGISTER_ENV, register_name.clone());
+                // Without a match, only the pid tells the program's window apart
+                let spawned = match &matching {
+                    Some(_) => target_action::spawn(socket, &spawn, scratchpad_env),
+                    None => target_action::spawn_process(&spawn, scratchpad_env).map(Some),
+                };
+                let pid = match spawned {
+                    Ok(pid) => pid,
+                    Err(error) => return Err(format!("failed to spawn: {error}")),
+                };
+                state.delete_register(&register_name);
+                state.add_pending_spawn(&register_name, pid, matching, as_float);
+                run_hook(
+                    &config.hooks,
+                    HookEvent::Spawn,
+                    HookContext::register_name(&register_name),
+                );
+                return Ok(String::new());
+            }
+            if snapshot.focused_workspace().is_none() {
+                return Ok(String::new());
+            }
 
-            match focused_window {
-                Some(window) => {
-                    let result = handle_focused_window(
-                        &mut socket,
+            let options = ToggleOptions {
+                mode,
+                focus_first,
+                as_float,
+                animations,
+                elsewhere,
+                destination,
+                placement,
+            };
+            let output_value = match snapshot.focused_window() {
+                Some(_) => handle_focused_window(
+                    socket,
+                    &snapshot,
+                    state,
+                    &register_name,
+                    output,
+                    options,
+                    &config.hooks,
+                )
+                .unwrap_or_default(),
+                None => {
+                    handle_no_focused_window(
+                        socket,
+                        &snapshot,
                         state,
-                        register_number,
-                        FocusedWindowContext {
-                            window_id: window.id,
-                            title: window.title,
-                            app_id: window.app_id,
-                            current_workspace_id: current_workspace.id,
-                        },
-                        output,
-                        as_float,
-                        animations,
+                        &register_name,
+                        options,
+                        &config.hooks,
                     );
-                    result.unwrap_or_default()
-                }
-                None => {
-                    handle_no_focused_window(&mut socket, state, register_number);
                     String::new()
                 }
+            };
+            match format {
+                Some(format) => {
+                    let snapshot = refresh(socket, snapshot, true);
+                    describe_register(&snapshot, state, &register_name)
+                        .map(|info| info.render(&format))
+                        .unwrap_or_default()
+                }
+                None => output_value,
             }
         }
+        Action::Show {
+            register_name,
+            destination,
+            placement,
+        } => {
+            show_register(
+                socket,
+                &snapshot,
+                state,
+                &register_name,
+                &destination,
+                &placement,
+                &config.hooks,
+            );
+            String::new()
+        }
+        Action::Hide { register_name } => {
+            hide_register(socket, &snapshot, state, &register_name, &config.hooks);
+            String::new()
+        }
         Action::Delete {
-            register_number,
+            register_name,
             output,
+            format,
         } => {
-            if output.is_some() {
-                String::new()
-            } else {
-                if register_check(&mut socket, state, register_number).is_some() {
-                    let Ok(_) = register_action::summon(
-                        &mut socket,
-                        state,
-                        RegisterInformation::Id(register_number),
-                    ) else {
-                        return Ok(());
-                    };
-                    state.delete_register(register_number);
-                }
-                String::new()
+            let Some(register_with_status) = register_check(&snapshot, state, &register_name)
+            else {
+                return Ok(String::new());
+            };
+            let summoned = matches!(register_with_status.status, RegisterStatus::WindowMapped);
+            let was_stashed = snapshot
+                .window(register_with_status.register.window_id)
+                .is_some_and(|window| snapshot.is_stashed(window));
+            if summoned {
+                register_action::summon(
+                    socket,
+                    &snapshot,
+                    state,
+                    RegisterInformation::Name(&register_name),
+                    &Destination::Focused,
+                    &PlacementOptions::default(),
+                );
+            }
+            let snapshot = refresh(socket, snapshot, summoned && format.is_some());
+            let info = describe_register(&snapshot, state, &register_name);
+            if was_stashed {
+                run_register_hook(&config.hooks, HookEvent::Summon, state, &register_name);
             }
+            run_register_hook(&config.hooks, HookEvent::Delete, state, &register_name);
+            state.delete_register(&register_name);
+            render_info(info, format, output)
         }
         Action::Get {
-            register_number,
+            register_name,
             output,
+            format,
         } => {
-            sync_state(&mut socket, state);
-            let Some(register) = state.get_register_by_number(register_number) else {
-                return write_response(&stream, "");
+            sync_state(&snapshot, state);
+            let Some(info) = describe_register(&snapshot, state, &register_name) else {
+                return Ok(String::new());
             };
-            match output {
-                Output::Title => register.title.unwrap_or_default(),
-                Output::AppId => register.app_id.unwrap_or_default(),
+            match (format, output) {
+                (Some(format), _) => info.render(&format),
+                (None, Some(output)) => info.render_output(&output),
+                (None, None) => info.render(&Format::Json),
             }
         }
+        Action::List { format } => {
+            sync_state(&snapshot, state);
+            let format = format.unwrap_or(Format::Template(DEFAULT_LIST_TEMPLATE.to_string()));
+            let infos: Vec<RegisterInfo> = state
+                .registers
+                .iter()
+                .map(|register| RegisterInfo::new(register, &snapshot))
+                .collect();
+            render_all(&infos, &format)
+        }
+        Action::Swap { register_name } => {
+            let held = state.get_register_ref_by_name(&register_name).cloned();
+            register_action::swap(socket, &snapshot, state, &register_name);
+            if state
+                .get_register_ref_by_name(&register_name)
+                .is_some_and(|register| {
+                    Some(register.window_id) != held.as_ref().map(|held| held.window_id)
+                })
+            {
+                // The window the register held until now is the one brought out
+                if let Some(held) = held.filter(|held| snapshot.window(held.window_id).is_some()) {
+                    run_hook(
+                        &config.hooks,
+                        HookEvent::Summon,
+                        HookContext::register(&held),
+                    );
+                }
+                run_register_hook(&config.hooks, HookEvent::Stash, state, &register_name);
+            }
+            String::new()
+        }
         Action::Sync => {
-            sync_state(&mut socket, state);
+            sync_state(&snapshot, state);
             String::new()
         }
+        Action::Rescue { adopt: true }
+        | Action::Adopt {
+            window_id: None,
+            all: true,
+            ..
+        } => {
+            sync_state(&snapshot, state);
+            let names =
+                register_action::adopt_stashed(&snapshot, state, &config.rules, &config.targets);
+            for name in &names {
+                run_register_hook(&config.hooks, HookEvent::Create, state, name);
+            }
+            describe_registers(&snapshot, state, &names)
+        }
+        Action::Rescue { adopt: false } => {
+            let rescued = register_action::rescue(socket, &snapshot, state);
+            for register in state
+                .registers
+                .iter()
+                .filter(|register| rescued.iter().any(|window| window.id == register.window_id))
+            {
+                run_hook(
+                    &config.hooks,
+                    HookEvent::Summon,
+                    HookContext::register(register),
+                );
+            }
+            window_lines(&rescued)
+        }
+        Action::Adopt {
+            window_id,
+            register_name,
+            ..
+        } => {
+            let (Some(window_id), Some(register_name)) = (window_id, register_name) else {
+                return Err("adopt needs a window id and a register, or --all".to_string());
+            };
+            sync_state(&snapshot, state);
+            adopt_window_by_id(&snapshot, state, window_id, &register_name)?;
+            run_register_hook(&config.hooks, HookEvent::Create, state, &register_name);
+            describe_registers(&snapshot, state, &[register_name])
+        }
+        Action::Pick { chosen: None, .. } => {
+            sync_state(&snapshot, state);
+            pick::lines(&snapshot, state, &config.targets)
+        }
+        Action::Pick {
+            chosen: Some(line), ..
+        } => {
+            summon_picked(socket, &snapshot, state, &line, config)?;
+            String::new()
+        }
+        Action::Untracked => {
+            sync_state(&snapshot, state);
+            window_lines(&register_action::untracked_stashed(
+                &snapshot,
+                state,
+                &config.targets,
+            ))
+        }
         Action::Target {
             property,
             spawn,
             as_float,
             animations,
+            mode,
+            focus_first,
+            elsewhere,
+            destination,
+            placement,
         } => {
-            let _ = handle_target(property, spawn, as_float, animations);
-            return Ok(());
+            state.add_target(property.clone());
+            let spawn = spawn
+                .requested()
+                .filter(|_| !state.is_target_pending(&property));
+            if let Ok(TargetOutcome::Spawned) = handle_target(
+                socket,
+                &snapshot,
+                property.clone(),
+                spawn,
+                ToggleOptions {
+                    mode,
+                    focus_first,
+                    as_float,
+                    animations,
+                    elsewhere,
+                    destination,
+                    placement,
+                },
+                &state.stash_origins,
+            ) {
+                run_hook(
+                    &config.hooks,
+                    HookEvent::Spawn,
+                    HookContext::target(&property),
+                );
+                state.add_pending_target(property);
+            }
+            String::new()
         }
     };
 
-    write_response(&stream, &response)
+    Ok(response)
 }
 
-fn write_response(stream: &UnixStream, response: &str) -> Result<()> {
+/// Takes a new snapshot when `moved` says windows were moved since `snapshot` and the result is
+/// about to be printed. Keeps the old one if niri does not answer.
+fn refresh(socket: &mut Socket, snapshot: Snapshot, moved: bool) -> Snapshot {
+    if !moved {
+        return snapshot;
+    }
+    Snapshot::take(socket, &snapshot.stash_name).unwrap_or(snapshot)
+}
+
+/// Asks the running daemon behind `stream` for its registers, the daemon exits once it has answered.
+fn take_over(stream: UnixStream) -> Result<State> {
+    let response = Client::exchange(stream, &Action::Handover)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+fn write_response(stream: &UnixStream, response: Response) -> Result<()> {
     let mut writer = stream;
-    writeln!(writer, "{}", response)?;
+    writeln!(writer, "{}", serde_json::to_string(&response)?)?;
     Ok(())
 }
 
-fn register_check(
+/// Renders what `--format` or `--output` asked for, nothing when neither was given.
+fn render_info(
+    info: Option<RegisterInfo>,
+    format: Option<Format>,
+    output: Option<Output>,
+) -> String {
+    match (info, format, output) {
+        (Some(info), Some(format), _) => info.render(&format),
+        (Some(info), None, Some(output)) => info.render_output(&output),
+        _ => String::new(),
+    }
+}
+
+/// Binds the first window matching `property` that no other register holds, wherever it is and
+/// without focusing it.
+fn bind_matching_window(
     socket: &mut Socket,
+    snapshot: &Snapshot,
+    state: &mut State,
+    register_name: &str,
+    property: &Property,
+    as_float: bool,
+) -> std::result::Result<(), String> {
+    let window = get_windows_by_property(snapshot, property)
+        .windows
+        .into_iter()
+        .find(|window| {
+            !state
+                .registers
+                .iter()
+                .any(|register| register.window_id == window.id && register.name != register_name)
+        })
+        .ok_or_else(|| format!("no window matches {property}"))?;
+
+    state.delete_register(register_name);
+    state.registers.push(Register {
+        title: window.title,
+        app_id: window.app_id,
+        window_id: window.id,
+        name: register_name.to_string(),
+        rule: None,
+    });
+    if as_float && !window.is_floating {
+        set_floating(socket, window.id);
+    }
+    Ok(())
+}
+
+/// Binds the window `window_id` to the register, unless another register holds it.
+fn adopt_window_by_id(
+    snapshot: &Snapshot,
+    state: &mut State,
+    window_id: u64,
+    register_name: &str,
+) -> std::result::Result<(), String> {
+    let window = snapshot
+        .window(window_id)
+        .ok_or_else(|| format!("no window with id {window_id}"))?;
+    if let Some(register) = state
+        .registers
+        .iter()
+        .find(|register| register.window_id == window_id && register.name != register_name)
+    {
+        return Err(format!(
+            "window {window_id} is held by register {}",
+            register.name
+        ));
+    }
+
+    state.delete_register(register_name);
+    state.registers.push(Register {
+        title: window.title.clone(),
+        app_id: window.app_id.clone(),
+        window_id: window.id,
+        name: register_name.to_string(),
+        rule: None,
+    });
+    Ok(())
+}
+
+/// Renders the named registers the way `list` does by default.
+fn describe_registers(snapshot: &Snapshot, state: &State, register_names: &[String]) -> String {
+    let infos: Vec<RegisterInfo> = register_names
+        .iter()
+        .filter_map(|name| state.get_register_ref_by_name(name))
+        .map(|register| RegisterInfo::new(register, snapshot))
+        .collect();
+    render_all(&infos, &Format::Template(DEFAULT_LIST_TEMPLATE.to_string()))
+}
+
+/// One `{window_id}\t{app_id}\t{title}` line per window.
+fn window_lines(windows: &[Window]) -> String {
+    windows
+        .iter()
+        .map(|window| {
+            format!(
+                "{}\t{}\t{}",
+                window.id,
+                window.app_id.as_deref().unwrap_or_default(),
+                window.title.as_deref().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn describe_register(
+    snapshot: &Snapshot,
     state: &State,
-    register_number: i32,
+    register_name: &str,
+) -> Option<RegisterInfo> {
+    let register = state.get_register_ref_by_name(register_name)?;
+    Some(RegisterInfo::new(register, snapshot))
+}
+
+fn register_check(
+    snapshot: &Snapshot,
+    state: &State,
+    register_name: &str,
 ) -> Option<RegisterWithStatus> {
-    let register = state.get_register_by_number(register_number)?;
+    let register = state.get_register_by_name(register_name)?;
     Some(RegisterWithStatus {
-        status: register_action::check_status(socket, &register),
+        status: register_action::check_status(snapshot, &register),
         register,
     })
 }
 
 fn handle_focused_window(
     socket: &mut Socket,
+    snapshot: &Snapshot,
     state: &mut State,
-    register_number: i32,
-    context: FocusedWindowContext,
+    register_name: &str,
     output: Option<Output>,
-    as_float: bool,
-    animations: bool,
+    options: ToggleOptions,
+    hooks: &Hooks,
 ) -> Option<String> {
-    match register_check(socket, state, register_number) {
+    let focused_window = snapshot.focused_window()?;
+    let ToggleOptions {
+        mode,
+        focus_first,
+        as_float,
+        animations,
+        elsewhere,
+        destination,
+        placement,
+    } = options;
+    match register_check(snapshot, state, register_name) {
         Some(register_with_status) => match register_with_status.status {
             RegisterStatus::WindowMapped => {
-                let Ok(Ok(NiriResponse::Windows(windows))) = socket.send(NiriRequest::Windows)
-                else {
-                    return None;
-                };
-                let register_window = windows
-                    .iter()
-                    .find(|w| w.id == register_with_status.register.window_id)?;
+                let register_window = snapshot.window(register_with_status.register.window_id)?;
 
                 let output_value = match output {
                     Some(Output::Title) => register_window.title.clone(),
@@ -201,32 +810,75 @@ fn handle_focused_window(
                 };
 
                 state.update_register(Register {
-                    number: register_number,
                     title: register_window.title.clone(),
                     app_id: register_window.app_id.clone(),
                     window_id: register_window.id,
+                    ..register_with_status.register.clone()
                 });
 
                 let Some(workspace_id) = register_window.workspace_id else {
                     return output_value;
                 };
 
-                if workspace_id == context.current_workspace_id {
+                // With a destination other than the focused workspace, that is where it counts
+                // as visible
+                let origin = state.stash_origins.get(&register_window.id).copied();
+                let on_current_workspace = snapshot
+                    .destination(&destination, origin)
+                    .is_some_and(|workspace| workspace.id == workspace_id);
+                if focus_first
+                    && on_current_workspace
+                    && mode != Mode::Hide
+                    && register_window.id != focused_window.id
+                {
+                    let _ = socket.send(NiriRequest::Action(FocusWindow {
+                        id: register_window.id,
+                    }));
+                    return output_value;
+                }
+                // Neither visible here nor stashed, so `elsewhere` decides
+                let is_elsewhere = !on_current_workspace && !snapshot.is_stashed(register_window);
+                if is_elsewhere && mode != Mode::Hide && elsewhere == Elsewhere::Goto {
+                    let _ = socket.send(NiriRequest::Action(FocusWindow {
+                        id: register_window.id,
+                    }));
+                    return output_value;
+                }
+                let stash = match mode {
+                    Mode::Toggle => {
+                        on_current_workspace || is_elsewhere && elsewhere == Elsewhere::Stash
+                    }
+                    Mode::Show if on_current_workspace => return output_value,
+                    Mode::Show if is_elsewhere && elsewhere == Elsewhere::Stash => {
+                        return output_value;
+                    }
+                    Mode::Show => false,
+                    Mode::Hide if snapshot.is_stashed(register_window) => {
+                        return output_value;
+                    }
+                    Mode::Hide => true,
+                };
+                if stash {
                     if animations && register_window.is_floating {
                         set_tiling(socket, register_window.id);
                     }
                     register_action::stash(
                         socket,
+                        snapshot,
                         state,
-                        Some(register_with_status.register.number),
+                        Some(&register_with_status.register.name),
                     );
+                    run_register_hook(hooks, HookEvent::Stash, state, register_name);
                 } else {
                     register_action::summon(
                         socket,
+                        snapshot,
                         state,
                         RegisterInformation::Register(&register_with_status.register),
-                    )
-                    .ok();
+                        &destination,
+                        &placement,
+                    );
+                    run_register_hook(hooks, HookEvent::Summon, state, register_name);
 
                     if as_float && animations {
                         set_floating(socket, register_window.id);
@@ -236,26 +888,28 @@ fn handle_focused_window(
                 output_value
             }
             RegisterStatus::WindowDropped => {
-                state.delete_register(register_number);
+                state.delete_register(register_name);
 
                 let output_value = if let Some(output) = output {
                     match output {
-                        Output::Title => context.title.clone(),
-                        Output::AppId => context.app_id.clone(),
+                        Output::Title => focused_window.title.clone(),
+                        Output::AppId => focused_window.app_id.clone(),
                     }
                 } else {
                     None
                 };
 
                 state.registers.push(Register {
-                    title: context.title,
-                    app_id: context.app_id,
-                    window_id: context.window_id,
-                    number: register_number,
+                    title: focused_window.title.clone(),
+                    app_id: focused_window.app_id.clone(),
+                    window_id: focused_window.id,
+                    name: register_name.to_string(),
+                    rule: None,
                 });
+                run_register_hook(hooks, HookEvent::Create, state, register_name);
 
                 if as_float {
-                    set_floating(socket, context.window_id);
+                    set_floating(socket, focused_window.id);
                 }
 
                 output_value
@@ -263,39 +917,186 @@ fn handle_focused_window(
         },
         None => {
             state.registers.push(Register {
-                title: context.title,
-                app_id: context.app_id,
-                window_id: context.window_id,
-                number: register_number,
+                title: focused_window.title.clone(),
+                app_id: focused_window.app_id.clone(),
+                window_id: focused_window.id,
+                name: register_name.to_string(),
+                rule: None,
             });
+            run_register_hook(hooks, HookEvent::Create, state, register_name);
             if as_float {
-                set_floating(socket, context.window_id);
+                set_floating(socket, focused_window.id);
             }
             None
         }
     }
 }
 
-fn handle_no_focused_window(socket: &mut Socket, state: &State, register_number: i32) {
-    let Some(register) = state.registers.iter().find(|r| r.number == register_number) else {
+/// Toggles a register while the focused workspace is empty, so its window is stashed, on the
+/// destination workspace or elsewhere.
+fn handle_no_focused_window(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    state: &State,
+    register_name: &str,
+    options: ToggleOptions,
+    hooks: &Hooks,
+) {
+    let Some(register) = state.registers.iter().find(|r| r.name == register_name) else {
+        return;
+    };
+    // A closed window is neither stashed nor summoned
+    let Some(window) = snapshot.window(register.window_id) else {
         return;
     };
+    let origin = state.stash_origins.get(&register.window_id).copied();
+    let on_destination = snapshot
+        .destination(&options.destination, origin)
+        .is_some_and(|workspace| window.workspace_id == Some(workspace.id));
+    let is_stashed = snapshot.is_stashed(window);
+    let is_elsewhere = !on_destination && !is_stashed;
+    let stash = match (options.mode, options.elsewhere) {
+        (Mode::Hide, _) if is_stashed => return,
+        (Mode::Hide, _) => true,
+        (Mode::Toggle, _) if on_destination => true,
+        (Mode::Show, _) if on_destination => return,
+        (_, Elsewhere::Goto) if is_elsewhere => {
+            let _ = socket.send(NiriRequest::Action(FocusWindow {
+                id: register.window_id,
+            }));
+            return;
+        }
+        (Mode::Toggle, Elsewhere::Stash) if is_elsewhere => true,
+        (Mode::Show, Elsewhere::Stash) if is_elsewhere => return,
+        _ => false,
+    };
+    if stash {
+        register_action::stash(socket, snapshot, state, Some(register_name));
+        run_hook(hooks, HookEvent::Stash, HookContext::register(register));
+        return;
+    }
 
-    register_action::summon(socket, state, RegisterInformation::Register(register)).ok();
+    register_action::summon(
+        socket,
+        snapshot,
+        state,
+        RegisterInformation::Register(register),
+        &options.destination,
+        &options.placement,
+    );
+    run_hook(hooks, HookEvent::Summon, HookContext::register(register));
 }
 
-fn sync_state(socket: &mut Socket, state: &mut State) {
-    let tracked_registers = state.get_tracked_registers();
-    let Ok(register_statuses) = register_action::get_all_register_status(socket, tracked_registers)
-    else {
+/// Summons a register's window unless it already is on the destination workspace. An empty
+/// register is left empty.
+fn show_register(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    state: &State,
+    register_name: &str,
+    destination: &Destination,
+    placement: &PlacementOptions,
+    hooks: &Hooks,
+) {
+    let Some(register) = state.get_register_ref_by_name(register_name) else {
         return;
     };
-    state.syncronize_registers(register_statuses).ok();
+    let Some(window) = snapshot.window(register.window_id) else {
+        return;
+    };
+    let origin = state.stash_origins.get(&window.id).copied();
+    let Some(destination_workspace) = snapshot.destination(destination, origin) else {
+        return;
+    };
+    if window.workspace_id != Some(destination_workspace.id) {
+        register_action::summon(
+            socket,
+            snapshot,
+            state,
+            RegisterInformation::Register(register),
+            destination,
+            placement,
+        );
+        run_hook(hooks, HookEvent::Summon, HookContext::register(register));
+    }
+}
+
+/// Stashes a register's window unless it already is in the stash.
+fn hide_register(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    state: &State,
+    register_name: &str,
+    hooks: &Hooks,
+) {
+    let Some(register) = state.get_register_ref_by_name(register_name) else {
+        return;
+    };
+    if snapshot
+        .window(register.window_id)
+        .is_some_and(|window| !snapshot.is_stashed(window))
+    {
+        register_action::stash(socket, snapshot, state, Some(register_name));
+        run_hook(hooks, HookEvent::Stash, HookContext::register(register));
+    }
 }
 
-fn get_socket_path() -> Result<PathBuf> {
-    let runtime_dir = var("XDG_RUNTIME_DIR").map_err(|_| {
-        std::io::Error::new(std::io::ErrorKind::NotFound, "XDG_RUNTIME_DIR not set")
-    })?;
-    Ok(PathBuf::from(runtime_dir).join("niri-register.sock"))
+/// Summons the register or target named by a line of [`pick::lines`], or focuses it when it is
+/// on the focused workspace already.
+fn summon_picked(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    state: &State,
+    line: &str,
+    config: &Config,
+) -> std::result::Result<(), String> {
+    match pick::find(state, &config.targets, line)? {
+        PickEntry::Register(register_name) => {
+            let Some(register) = state.get_register_ref_by_name(register_name) else {
+                return Ok(());
+            };
+            if snapshot.window(register.window_id).is_none() {
+                return Err(format!("the window of register {register_name} is closed"));
+            }
+            register_action::summon(
+                socket,
+                snapshot,
+                state,
+                RegisterInformation::Register(register),
+                &Destination::Focused,
+                &PlacementOptions::default(),
+            );
+            run_hook(
+                &config.hooks,
+                HookEvent::Summon,
+                HookContext::register(register),
+            );
+        }
+        PickEntry::Target(property) => {
+            handle_target(
+                socket,
+                snapshot,
+                property,
+                None,
+                ToggleOptions {
+                    mode: Mode::Show,
+                    focus_first: true,
+                    as_float: false,
+                    animations: false,
+                    elsewhere: Elsewhere::Bring,
+                    destination: Destination::Focused,
+                    placement: PlacementOptions::default(),
+                },
+                &state.stash_origins,
+            )
+            .map_err(|error| error.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn sync_state(snapshot: &Snapshot, state: &mut State) {
+    let tracked_registers = state.get_tracked_registers();
+    let register_statuses = register_action::get_all_register_status(snapshot, tracked_registers);
+    state.syncronize_registers(register_statuses).ok();
 }
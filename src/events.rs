@@ -0,0 +1,259 @@
+//! Follows niri's event stream so the daemon can bind windows to registers as they open.
+
+use crate::config::Rule;
+use crate::hooks::{HookContext, HookEvent, run_hook, run_register_hook};
+use crate::register_action::adopt_window;
+use crate::reload::SharedConfig;
+use crate::snapshot::Snapshot;
+use crate::sockets::SocketPaths;
+use crate::state::{Register, State};
+use crate::target_action::stash_window;
+use crate::utils::{ensure_stash_workspace, set_floating};
+use niri_ipc::{Event, Request, Response, Window, Workspace, socket::Socket};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Error, Result},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::Instant,
+};
+
+struct EventListener {
+    socket: Socket,
+    config: SharedConfig,
+    state: Arc<Mutex<State>>,
+    workspaces: Vec<Workspace>,
+    /// Open windows and the workspace each one is on.
+    open_windows: HashMap<u64, Option<u64>>,
+    /// When each open window was first seen, only windows seen after a spawn can be its window.
+    first_seen: HashMap<u64, Instant>,
+    /// Windows that were already open when the daemon started. Rules bind them where they are.
+    preexisting_windows: Option<HashSet<u64>>,
+    /// Open windows a rule has bound once, they are not claimed again after their register is
+    /// deleted.
+    claimed_windows: HashSet<u64>,
+}
+
+/// Subscribes to niri's event stream and handles events on a thread of their own.
+pub fn spawn_event_listener(
+    paths: &SocketPaths,
+    config: SharedConfig,
+    state: Arc<Mutex<State>>,
+) -> Result<()> {
+    let mut event_socket = paths.connect_niri()?;
+    let Ok(Response::Handled) = event_socket.send(Request::EventStream)? else {
+        return Err(Error::other("niri refused the event stream"));
+    };
+    let mut listener = EventListener {
+        socket: paths.connect_niri()?,
+        config,
+        state,
+        workspaces: vec![],
+        open_windows: HashMap::new(),
+        first_seen: HashMap::new(),
+        preexisting_windows: None,
+        claimed_windows: HashSet::new(),
+    };
+
+    thread::spawn(move || {
+        let mut read_event = event_socket.read_events();
+        while let Ok(event) = read_event() {
+            listener.handle(event);
+        }
+        eprintln!("niri event stream closed");
+    });
+    Ok(())
+}
+
+impl EventListener {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::WorkspacesChanged { workspaces } => self.workspaces = workspaces,
+            Event::WindowsChanged { windows } => {
+                self.open_windows = windows
+                    .iter()
+                    .map(|window| (window.id, window.workspace_id))
+                    .collect();
+                self.first_seen
+                    .retain(|id, _| self.open_windows.contains_key(id));
+                for window in &windows {
+                    self.first_seen
+                        .entry(window.id)
+                        .or_insert_with(Instant::now);
+                }
+                if self.preexisting_windows.is_none() {
+                    self.preexisting_windows = Some(self.open_windows.keys().copied().collect());
+                }
+                for window in &windows {
+                    self.apply_rules(window);
+                }
+            }
+            Event::WindowOpenedOrChanged { window } => {
+                let first_seen = *self
+                    .first_seen
+                    .entry(window.id)
+                    .or_insert_with(Instant::now);
+                // A window a static target was waiting for is not one of the register spawns
+                let is_target_window = self
+                    .state
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .resolve_pending_targets(&window, first_seen);
+                let previous_workspace = self.open_windows.insert(window.id, window.workspace_id);
+                if let Some(Some(origin)) = previous_workspace {
+                    self.window_moved(&window, origin);
+                }
+                if previous_workspace.is_none()
+                    && !is_target_window
+                    && self.bind_pending_spawn(&window)
+                {
+                    return;
+                }
+                self.apply_rules(&window);
+            }
+            Event::WindowClosed { id } => {
+                self.open_windows.remove(&id);
+                self.claimed_windows.remove(&id);
+                self.first_seen.remove(&id);
+                let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+                state.stash_origins.remove(&id);
+                let hooks = self.config.get().hooks;
+                for register in state
+                    .registers
+                    .iter()
+                    .filter(|register| register.window_id == id)
+                {
+                    run_hook(&hooks, HookEvent::Drop, HookContext::register(register));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Remembers where a window that just moved into the stash came from, so `rescue` can put it
+    /// back there. This sees every move, including ones made with niri's own binds, so windows
+    /// nothing tracks are reported or adopted here.
+    fn window_moved(&self, window: &Window, origin: u64) {
+        let config = self.config.get();
+        let Some(stash_workspace) = self
+            .workspaces
+            .iter()
+            .find(|workspace| workspace.name.as_deref() == Some(config.stash.workspace.as_str()))
+        else {
+            return;
+        };
+        if window.workspace_id != Some(stash_workspace.id) || origin == stash_workspace.id {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.stash_origins.insert(window.id, origin);
+        if state.is_tracked(window, &config.targets) {
+            return;
+        }
+        if config.auto_adopt {
+            let name = adopt_window(&mut state, window, &config.rules);
+            eprintln!("Adopted stashed window {} into register {name}", window.id);
+            run_register_hook(&config.hooks, HookEvent::Create, &state, &name);
+        } else {
+            eprintln!(
+                "Window {} was stashed without a register, use `adopt` to toggle it",
+                window.id
+            );
+        }
+    }
+
+    /// Binds a newly opened window to the register whose spawn it belongs to.
+    fn bind_pending_spawn(&mut self, window: &Window) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(pending) = state.take_pending_spawn(window) else {
+            return false;
+        };
+        state.delete_register(&pending.register_name);
+        let register = Register {
+            title: window.title.clone(),
+            app_id: window.app_id.clone(),
+            window_id: window.id,
+            name: pending.register_name,
+            rule: None,
+        };
+        run_hook(
+            &self.config.get().hooks,
+            HookEvent::Create,
+            HookContext::register(&register),
+        );
+        state.registers.push(register);
+        if pending.as_float && !window.is_floating {
+            set_floating(&mut self.socket, window.id);
+        }
+        true
+    }
+
+    fn apply_rules(&mut self, window: &Window) {
+        if self.claimed_windows.contains(&window.id) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state
+            .registers
+            .iter()
+            .any(|register| register.window_id == window.id)
+        {
+            return;
+        }
+        let config = self.config.get();
+        let Some(rule) = config
+            .rules
+            .iter()
+            .find(|rule| rule.matches(window) && self.is_register_free(&state, rule))
+        else {
+            return;
+        };
+
+        self.claimed_windows.insert(window.id);
+        state.delete_register(&rule.register);
+        let register = Register {
+            title: window.title.clone(),
+            app_id: window.app_id.clone(),
+            window_id: window.id,
+            name: rule.register.clone(),
+            rule: Some(rule.name().to_string()),
+        };
+        run_hook(
+            &config.hooks,
+            HookEvent::Create,
+            HookContext::register(&register),
+        );
+        state.registers.push(register);
+
+        if self
+            .preexisting_windows
+            .as_ref()
+            .is_some_and(|preexisting| preexisting.contains(&window.id))
+        {
+            return;
+        }
+        if rule.float && !window.is_floating {
+            set_floating(&mut self.socket, window.id);
+        }
+        if rule.stash
+            && let Ok(mut snapshot) = Snapshot::take(&mut self.socket, &config.stash.workspace)
+        {
+            // No client waits on a rule, the daemon's stderr is the only place to tell
+            let (stash_workspace_id, message) =
+                ensure_stash_workspace(&mut self.socket, &mut snapshot, &config.stash);
+            if let Some(message) = message {
+                eprintln!("{message}");
+            }
+            if let Some(stash_workspace_id) = stash_workspace_id {
+                stash_window(&mut self.socket, window, stash_workspace_id);
+            }
+        }
+    }
+
+    /// A register is free when it is unset or its window has closed.
+    fn is_register_free(&self, state: &State, rule: &Rule) -> bool {
+        state
+            .get_register_ref_by_name(&rule.register)
+            .is_none_or(|register| !self.open_windows.contains_key(&register.window_id))
+    }
+}
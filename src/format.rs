@@ -0,0 +1,193 @@
+//! Register information as printed by `--format`.
+
+use crate::args::{Format, Output};
+use crate::snapshot::Snapshot;
+use crate::state::Register;
+use serde::Serialize;
+
+/// Snapshot of a register and its window.
+#[derive(Debug, Serialize)]
+pub struct RegisterInfo {
+    /// The register's name.
+    pub name: String,
+    /// Id of the register's window.
+    pub window_id: u64,
+    /// The window's app id, as niri reports it or as last seen when the window is gone.
+    pub app_id: Option<String>,
+    /// The window's title, as niri reports it or as last seen when the window is gone.
+    pub title: Option<String>,
+    /// Process id of the window's client.
+    pub pid: Option<i32>,
+    /// Name of the window's workspace, or its index when it has no name.
+    pub workspace: Option<String>,
+    /// Connector name of the output the window's workspace is on.
+    pub output: Option<String>,
+    /// Whether the window is floating.
+    pub floating: bool,
+    /// Whether the window is on the stash workspace.
+    pub stashed: bool,
+    /// Name of the config rule that created the register, if any.
+    pub rule: Option<String>,
+}
+
+impl RegisterInfo {
+    /// Combines a register with what niri reported about its window. Falls back to the register's
+    /// own title and app id when the window is gone.
+    pub fn new(register: &Register, snapshot: &Snapshot) -> Self {
+        let window = snapshot.window(register.window_id);
+        let workspace = window.and_then(|window| snapshot.workspace_of(window));
+        RegisterInfo {
+            name: register.name.clone(),
+            window_id: register.window_id,
+            app_id: window
+                .map(|window| window.app_id.clone())
+                .unwrap_or_else(|| register.app_id.clone()),
+            title: window
+                .map(|window| window.title.clone())
+                .unwrap_or_else(|| register.title.clone()),
+            pid: window.and_then(|window| window.pid),
+            workspace: workspace.map(|workspace| {
+                workspace
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| workspace.idx.to_string())
+            }),
+            output: workspace.and_then(|workspace| workspace.output.clone()),
+            floating: window.is_some_and(|window| window.is_floating),
+            stashed: window.is_some_and(|window| snapshot.is_stashed(window)),
+            rule: register.rule.clone(),
+        }
+    }
+
+    /// Renders this register as JSON or through a template.
+    pub fn render(&self, format: &Format) -> String {
+        match format {
+            Format::Json => serde_json::to_string(self).unwrap_or_default(),
+            Format::Template(template) => self.fill_template(template),
+        }
+    }
+
+    /// Renders the single property selected with `--output`.
+    pub fn render_output(&self, output: &Output) -> String {
+        match output {
+            Output::Title => self.title.clone().unwrap_or_default(),
+            Output::AppId => self.app_id.clone().unwrap_or_default(),
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        Some(match name {
+            "name" => self.name.clone(),
+            "window_id" => self.window_id.to_string(),
+            "app_id" => optional(&self.app_id),
+            "title" => optional(&self.title),
+            "pid" => self.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+            "workspace" => optional(&self.workspace),
+            "output" => optional(&self.output),
+            "floating" => self.floating.to_string(),
+            "stashed" => self.stashed.to_string(),
+            "rule" => optional(&self.rule),
+            _ => return None,
+        })
+    }
+
+    /// Replaces `{field}` placeholders in one pass, so titles containing braces are left alone.
+    /// Unknown placeholders are kept as written.
+    fn fill_template(&self, template: &str) -> String {
+        let mut rendered = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            match after
+                .find('}')
+                .and_then(|end| Some((end, self.field(&after[..end])?)))
+            {
+                Some((end, value)) => {
+                    rendered.push_str(&value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = after;
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// Renders several registers, as a JSON array or one template line per register.
+pub fn render_all(infos: &[RegisterInfo], format: &Format) -> String {
+    match format {
+        Format::Json => serde_json::to_string(infos).unwrap_or_default(),
+        Format::Template(_) => infos
+            .iter()
+            .map(|info| info.render(format))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> RegisterInfo {
+        RegisterInfo {
+            name: "term".to_string(),
+            window_id: 7,
+            app_id: Some("foot".to_string()),
+            title: Some("{title} of htop".to_string()),
+            pid: None,
+            workspace: Some("stash".to_string()),
+            output: None,
+            floating: true,
+            stashed: false,
+            rule: None,
+        }
+    }
+
+    #[test]
+    fn template_fills_fields() {
+        let format: Format = "{name} {window_id} {app_id} {floating} {stashed}"
+            .parse()
+            .unwrap();
+        assert_eq!(info().render(&format), "term 7 foot true false");
+    }
+
+    #[test]
+    fn template_leaves_missing_values_empty() {
+        let format: Format = "[{pid}] [{output}] [{rule}]".parse().unwrap();
+        assert_eq!(info().render(&format), "[] [] []");
+    }
+
+    #[test]
+    fn template_does_not_expand_braces_in_values() {
+        let format: Format = "{title}: {app_id}".parse().unwrap();
+        assert_eq!(info().render(&format), "{title} of htop: foot");
+    }
+
+    #[test]
+    fn template_keeps_unknown_and_unclosed_placeholders() {
+        let format: Format = "{nope} {name} {".parse().unwrap();
+        assert_eq!(info().render(&format), "{nope} term {");
+    }
+
+    #[test]
+    fn json_format_serializes_the_register() {
+        let json = info().render(&"json".parse().unwrap());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["name"], "term");
+        assert_eq!(value["window_id"], 7);
+        assert_eq!(value["pid"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn template_renders_one_line_per_register() {
+        let format: Format = "{name}".parse().unwrap();
+        assert_eq!(render_all(&[info(), info()], &format), "term\nterm");
+    }
+}
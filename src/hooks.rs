@@ -0,0 +1,150 @@
+//! Commands the daemon runs when a scratchpad changes, configured in the `[hooks]` table. Each
+//! command runs through `sh -c` with the event in environment variables and as JSON on stdin.
+
+use crate::args::Property;
+use crate::config::Hooks;
+use crate::state::{Register, State};
+use crate::target_action::{REGISTER_ENV, TARGET_ENV};
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    io::Write,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+};
+
+/// Environment variable holding the [`HookEvent`] a hook runs for.
+pub const EVENT_ENV: &str = "NIRI_SCRATCHPAD_EVENT";
+pub const WINDOW_ID_ENV: &str = "NIRI_SCRATCHPAD_WINDOW_ID";
+pub const APP_ID_ENV: &str = "NIRI_SCRATCHPAD_APP_ID";
+pub const TITLE_ENV: &str = "NIRI_SCRATCHPAD_TITLE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    /// A register was bound to a window.
+    Create,
+    /// A register was deleted with `delete`.
+    Delete,
+    Summon,
+    Stash,
+    /// The window of a register closed.
+    Drop,
+    /// A program was launched for a static target or a register.
+    Spawn,
+}
+
+impl Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HookEvent::Create => "create",
+            HookEvent::Delete => "delete",
+            HookEvent::Summon => "summon",
+            HookEvent::Stash => "stash",
+            HookEvent::Drop => "drop",
+            HookEvent::Spawn => "spawn",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What a hook is told about the register or target an event happened to.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HookContext {
+    pub register: Option<String>,
+    pub target: Option<String>,
+    pub window_id: Option<u64>,
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+impl HookContext {
+    pub fn register(register: &Register) -> Self {
+        HookContext {
+            register: Some(register.name.clone()),
+            window_id: Some(register.window_id),
+            app_id: register.app_id.clone(),
+            title: register.title.clone(),
+            ..HookContext::default()
+        }
+    }
+
+    /// A register whose program was launched but has no window yet.
+    pub fn register_name(register_name: &str) -> Self {
+        HookContext {
+            register: Some(register_name.to_string()),
+            ..HookContext::default()
+        }
+    }
+
+    pub fn target(property: &Property) -> Self {
+        HookContext {
+            target: Some(property.to_string()),
+            ..HookContext::default()
+        }
+    }
+
+    fn envs(&self) -> Vec<(&'static str, String)> {
+        [
+            (REGISTER_ENV, self.register.clone()),
+            (TARGET_ENV, self.target.clone()),
+            (WINDOW_ID_ENV, self.window_id.map(|id| id.to_string())),
+            (APP_ID_ENV, self.app_id.clone()),
+            (TITLE_ENV, self.title.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: HookEvent,
+    #[serde(flatten)]
+    context: &'a HookContext,
+}
+
+/// Runs the hook configured for `event`, if any, on a thread of its own and returns that thread.
+/// Failures are logged.
+pub fn run_hook(hooks: &Hooks, event: HookEvent, context: HookContext) -> Option<JoinHandle<()>> {
+    let command = hooks.command(event)?.to_string();
+    Some(thread::spawn(move || {
+        if let Err(error) = run(&command, event, &context) {
+            eprintln!("The {event} hook failed: {error}");
+        }
+    }))
+}
+
+/// Runs the hook for `event` with what `state` holds for the register, if it is set.
+pub fn run_register_hook(hooks: &Hooks, event: HookEvent, state: &State, register_name: &str) {
+    if let Some(register) = state.get_register_ref_by_name(register_name) {
+        run_hook(hooks, event, HookContext::register(register));
+    }
+}
+
+fn run(command: &str, event: HookEvent, context: &HookContext) -> std::io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env(EVENT_ENV, event.to_string())
+        .envs(context.envs())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its stdin may exit before reading it
+        let _ = writeln!(
+            stdin,
+            "{}",
+            serde_json::to_string(&Payload { event, context })?
+        );
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        eprintln!("The {event} hook exited with {status}");
+    }
+    Ok(())
+}
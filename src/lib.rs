@@ -0,0 +1,45 @@
+//! Scratchpads for the niri compositor.
+//!
+//! Static scratchpads are windows matched by app id or title ([`target_action`]), dynamic
+//! scratchpads are windows bound to a named register that lives in the daemon ([`run_daemon`],
+//! [`state`]). Both hide windows by moving them to the `stash` workspace and summon them back onto
+//! the focused workspace.
+//!
+//! Other programs can either talk to the daemon through [`client::Client`], or drive niri
+//! directly with the stash, summon and match functions in [`register_action`] and
+//! [`target_action`]. They work on a [`snapshot::Snapshot`] of niri's windows and workspaces.
+//!
+//! How the daemon serves clients, follows niri's events, reloads its config and runs hooks is
+//! private to the crate, only [`run_daemon`] and the [`Activation`] it takes are exported for the
+//! `niri-scratchpad` binary.
+//!
+//! ```no_run
+//! use niri_scratchpad::{args::Action, client::Client, sockets::SocketPaths};
+//!
+//! let client = Client::new(SocketPaths::resolve(None, None)?);
+//! let registers = client.request(&Action::List { format: None })?;
+//! println!("{registers}");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod args;
+pub mod client;
+pub mod config;
+mod daemon;
+mod events;
+pub mod format;
+mod hooks;
+pub mod pick;
+pub mod register_action;
+mod reload;
+pub mod snapshot;
+pub mod sockets;
+pub mod state;
+mod systemd;
+pub mod target_action;
+mod utils;
+
+pub use daemon::run_daemon;
+pub use systemd::Activation;
@@ -1,65 +1,119 @@
-use clap::Parser;
-use std::env::var;
-use std::io::{BufRead, BufReader, Result, Write};
-use std::os::unix::net::UnixStream;
-
-use crate::target_action::handle_target;
-pub mod args;
-pub mod daemon;
-pub mod register_action;
-pub mod state;
-pub mod target_action;
-pub mod utils;
-
-fn connect_or_start_daemon(socket_path: &str) -> Result<UnixStream> {
-    if let Ok(stream) = UnixStream::connect(socket_path) {
-        return Ok(stream);
-    }
+//! The `niri-scratchpad` command: runs the daemon, or sends a command to it and prints the reply.
 
-    let exe = std::env::current_exe()?;
-    std::process::Command::new(exe).arg("daemon").spawn()?;
-
-    for _ in 0..40 {
-        std::thread::sleep(std::time::Duration::from_millis(50));
-        if let Ok(stream) = UnixStream::connect(socket_path) {
-            return Ok(stream);
-        }
-    }
+use clap::Parser;
+use std::io::{ErrorKind, Result};
 
-    Err(std::io::Error::new(
-        std::io::ErrorKind::NotConnected,
-        "Failed to start daemon",
-    ))
-}
+use niri_scratchpad::{
+    Activation,
+    args::{Action, Args},
+    client::Client,
+    config::Config,
+    pick, run_daemon,
+    sockets::SocketPaths,
+    target_action::{ToggleOptions, handle_target_once},
+};
 
 fn main() -> Result<()> {
-    if std::env::args().any(|arg| arg == "daemon") {
-        return daemon::run_daemon();
+    // SAFETY: nothing but this thread runs yet
+    let activation = unsafe { Activation::take() };
+    let args = Args::parse();
+    let paths = SocketPaths::resolve(args.socket, args.niri_socket)?;
+    if let Action::Daemon { replace } = args.action {
+        return match run_daemon(paths, args.config, replace, activation) {
+            Err(error)
+                if matches!(error.kind(), ErrorKind::AddrInUse | ErrorKind::InvalidInput) =>
+            {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+            result => result,
+        };
     }
-    let args = args::Args::parse();
-    if let args::Action::Target {
+    let client = Client::new(paths);
+    // A running daemon keeps track of targets that are still starting up, so let it handle them
+    if let Action::Target {
         property,
         spawn,
         as_float,
         animations,
-    } = args.action
+        mode,
+        focus_first,
+        elsewhere,
+        destination,
+        placement,
+    } = &args.action
+        && !client.is_daemon_running()
     {
-        handle_target(property, spawn, as_float, animations)?;
+        let config = Config::load(args.config.as_deref())?;
+        let options = ToggleOptions {
+            mode: *mode,
+            focus_first: *focus_first,
+            as_float: *as_float,
+            animations: *animations,
+            elsewhere: *elsewhere,
+            destination: destination.clone(),
+            placement: placement.clone(),
+        };
+        let spawn = spawn.clone().requested();
+        if let Some(message) =
+            handle_target_once(client.paths(), &config, property, spawn, options)?
+        {
+            eprintln!("{message}");
+        }
         return Ok(());
     }
-    let runtime_dir = var("XDG_RUNTIME_DIR").map_err(|_| {
-        std::io::Error::new(std::io::ErrorKind::NotFound, "XDG_RUNTIME_DIR not set")
-    })?;
-    let socket_path = format!("{}/niri-register.sock", runtime_dir);
-    let mut stream = connect_or_start_daemon(&socket_path)?;
-    let request = serde_json::to_string(&args.action)?;
-    writeln!(stream, "{}", request)?;
-
-    let mut reader = BufReader::new(&stream);
-    let mut response = String::new();
-    reader.read_line(&mut response)?;
+    client.ensure_daemon(&std::env::current_exe()?, args.config.as_deref())?;
+    if let Action::Watch = args.action {
+        return client.watch(|reply| match reply {
+            Ok(message) => println!("{message}"),
+            Err(message) => println!("error: {message}"),
+        });
+    }
+    let action = match args.action {
+        Action::Pick {
+            chosen: None,
+            launcher,
+            print: false,
+        } => {
+            let launcher = match launcher {
+                Some(launcher) => Some(launcher),
+                None => Config::load(args.config.as_deref())?.pick.launcher,
+            };
+            let list = Action::Pick {
+                chosen: None,
+                launcher: None,
+                print: true,
+            };
+            match launcher {
+                None => list,
+                Some(launcher) => {
+                    let lines = client.request(&list)?;
+                    let Some(chosen) = pick::choose(&launcher, &lines)? else {
+                        return Ok(());
+                    };
+                    Action::Pick {
+                        chosen: Some(chosen),
+                        launcher: None,
+                        print: false,
+                    }
+                }
+            }
+        }
+        action => action,
+    };
+    request(&client, &action)
+}
 
-    print!("{}", response.trim());
+/// Sends `action` to the daemon and prints its response, errors it reports end the process.
+fn request(client: &Client, action: &Action) -> Result<()> {
+    match client.request(action) {
+        Ok(response) => print!("{}", response.trim()),
+        Err(error) if error.kind() == ErrorKind::Other => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        Err(error) => return Err(error),
+    }
 
     Ok(())
 }
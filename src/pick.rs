@@ -0,0 +1,201 @@
+//! The list `pick` hands to a dmenu-style launcher: one tab separated line per register and per
+//! configured or toggled target, with the app id, title and status of its window. The launcher prints the chosen
+//! line, which is sent back to the daemon to summon that entry.
+
+use crate::args::Property;
+use crate::config::Target;
+use crate::snapshot::Snapshot;
+use crate::state::State;
+use crate::target_action::get_windows_by_property;
+use niri_ipc::Window;
+use std::{
+    io::{Read, Result, Write},
+    process::{Command, Stdio},
+    slice,
+};
+
+/// What a picked line names.
+pub enum PickEntry<'a> {
+    /// A register, by name.
+    Register(&'a str),
+    /// A static target.
+    Target(Property),
+}
+
+/// One line per register, then one per configured target and per other target the daemon has
+/// toggled, as `{kind}\t{name}\t{app_id}\t{title}\t{status}`. The status is `visible`,
+/// `stashed` or `closed`.
+pub fn lines(snapshot: &Snapshot, state: &State, configured: &[Target]) -> String {
+    let registers = state.registers.iter().map(|register| {
+        let window = snapshot.window(register.window_id);
+        line(
+            "register",
+            &register.name,
+            window.and_then(|window| window.app_id.as_deref()),
+            window.and_then(|window| window.title.as_deref()),
+            status(snapshot, window.map(slice::from_ref).unwrap_or_default()),
+        )
+    });
+    let targets = known_targets(state, configured)
+        .into_iter()
+        .map(|property| {
+            let windows = get_windows_by_property(snapshot, &property).windows;
+            let window = windows.first();
+            line(
+                "target",
+                &property.to_string(),
+                window.and_then(|window| window.app_id.as_deref()),
+                window.and_then(|window| window.title.as_deref()),
+                status(snapshot, &windows),
+            )
+        });
+    registers.chain(targets).collect::<Vec<_>>().join("\n")
+}
+
+/// The `[[target]]` entries of the config, then the targets toggled since the daemon started.
+fn known_targets(state: &State, configured: &[Target]) -> Vec<Property> {
+    let mut targets: Vec<Property> = configured.iter().map(Target::property).collect();
+    for property in &state.targets {
+        if !targets.contains(property) {
+            targets.push(property.clone());
+        }
+    }
+    targets
+}
+
+fn line(kind: &str, name: &str, app_id: Option<&str>, title: Option<&str>, status: &str) -> String {
+    format!(
+        "{kind}\t{name}\t{}\t{}\t{status}",
+        app_id.unwrap_or_default(),
+        title.unwrap_or_default()
+    )
+}
+
+/// `stashed` when any window is in the stash, as summoning brings those back.
+fn status(snapshot: &Snapshot, windows: &[Window]) -> &'static str {
+    if windows.iter().any(|window| snapshot.is_stashed(window)) {
+        "stashed"
+    } else if windows.is_empty() {
+        "closed"
+    } else {
+        "visible"
+    }
+}
+
+/// Looks up the register or target a line from [`lines`] names. Only the first two columns
+/// count, launchers that print less of the line are fine as long as they keep those.
+pub fn find<'a>(
+    state: &'a State,
+    configured: &[Target],
+    line: &str,
+) -> std::result::Result<PickEntry<'a>, String> {
+    let mut columns = line.trim_end_matches('\n').split('\t');
+    let (Some(kind), Some(name)) = (columns.next(), columns.next()) else {
+        return Err(format!("`{line}` is not a line `pick` printed"));
+    };
+    match kind {
+        "register" => state
+            .get_register_ref_by_name(name)
+            .map(|register| PickEntry::Register(&register.name))
+            .ok_or_else(|| format!("no register named {name}")),
+        "target" => known_targets(state, configured)
+            .into_iter()
+            .find(|property| property.to_string() == name)
+            .map(PickEntry::Target)
+            .ok_or_else(|| format!("no known target {name}")),
+        _ => Err(format!("`{line}` is not a line `pick` printed")),
+    }
+}
+
+/// Pipes `lines` through the shell command `launcher` and returns the line it printed, `None`
+/// when the user dismissed it.
+pub fn choose(launcher: &str, lines: &str) -> Result<Option<String>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(launcher)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A launcher that was dismissed right away may not read the list at all
+        let _ = writeln!(stdin, "{lines}");
+    }
+    let mut chosen = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut chosen)?;
+    }
+    // dmenu and its clones exit with 1 when dismissed
+    if !child.wait()?.success() || chosen.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(chosen.lines().next().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Register;
+
+    fn state() -> State {
+        let mut state = State::new();
+        state.registers.push(Register {
+            title: Some("htop".to_string()),
+            app_id: Some("foot".to_string()),
+            window_id: 1,
+            name: "term".to_string(),
+            rule: None,
+        });
+        state.add_target(Property::Title {
+            value: "Mail".to_string(),
+        });
+        state
+    }
+
+    fn configured() -> Vec<Target> {
+        vec![Target {
+            app_id: Some("firefox".to_string()),
+            title: None,
+        }]
+    }
+
+    #[test]
+    fn finds_registers_by_the_first_two_columns() {
+        let state = state();
+        let entry = find(&state, &[], "register\tterm\tfoot\thtop\tvisible\n").unwrap();
+        assert!(matches!(entry, PickEntry::Register("term")));
+        assert!(matches!(
+            find(&state, &[], "register\tterm").unwrap(),
+            PickEntry::Register("term")
+        ));
+    }
+
+    #[test]
+    fn finds_configured_and_toggled_targets() {
+        let state = state();
+        let configured = configured();
+        let Ok(PickEntry::Target(property)) = find(&state, &configured, "target\tappid=firefox")
+        else {
+            panic!("configured target not found");
+        };
+        assert_eq!(property, configured[0].property());
+        let Ok(PickEntry::Target(property)) = find(&state, &configured, "target\ttitle=Mail\t\t")
+        else {
+            panic!("toggled target not found");
+        };
+        assert_eq!(
+            property,
+            Property::Title {
+                value: "Mail".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_entries_and_foreign_lines() {
+        let state = state();
+        assert!(find(&state, &[], "register\tmissing").is_err());
+        assert!(find(&state, &[], "target\tappid=firefox").is_err());
+        assert!(find(&state, &[], "window\tterm").is_err());
+        assert!(find(&state, &[], "term").is_err());
+    }
+}
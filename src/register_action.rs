@@ -1,45 +1,48 @@
-use std::io::Result;
+//! Stashing, summoning and checking the windows bound to registers, the dynamic scratchpads the
+//! daemon keeps in [`State`].
 
+/// Whether a register's window still exists.
 pub enum RegisterStatus {
+    /// niri still reports the window.
     WindowMapped,
+    /// The window has closed.
     WindowDropped,
 }
 
+use crate::args::{Destination, PlacementOptions};
+use crate::config::{Rule, Target};
+use crate::snapshot::Snapshot;
 use crate::state::{Register, RegisterUpdate, State};
+use crate::target_action;
+use crate::utils::{set_floating, set_tiling};
 use niri_ipc::{
-    Action::{FocusWindow, MoveWindowToMonitor, MoveWindowToWorkspace},
-    Request, Response,
+    Action::{
+        ConsumeOrExpelWindowLeft, FocusWindow, MoveColumnToIndex, MoveFloatingWindow,
+        MoveWindowToMonitor, MoveWindowToWorkspace, MoveWindowUp, SetWindowWidth,
+    },
+    PositionChange, Request, SizeChange, Window,
     socket::Socket,
 };
 
-pub fn stash(socket: &mut Socket, state: &State, register_number: Option<i32>) {
-    let (windows, workspaces) = match (
-        socket.send(Request::Windows),
-        socket.send(Request::Workspaces),
-    ) {
-        (Ok(Ok(Response::Windows(windows))), Ok(Ok(Response::Workspaces(workspaces)))) => {
-            (windows, workspaces)
-        }
-        _ => {
-            return;
-        }
-    };
-    let Some(stash_workspace) = workspaces
-        .iter()
-        .find(|workspace| workspace.name.as_deref() == Some("stash"))
-    else {
+/// Moves the window of `register_name`, or of every register when `None`, to the stash workspace.
+pub fn stash(socket: &mut Socket, snapshot: &Snapshot, state: &State, register_name: Option<&str>) {
+    let Some(stash_workspace) = snapshot.stash_workspace() else {
         return;
     };
-    for window in windows.iter().filter(|window| match register_number {
-        Some(register_num) => state
-            .registers
-            .iter()
-            .any(|register| register.number == register_num && register.window_id == window.id),
-        None => state
-            .registers
-            .iter()
-            .any(|register| register.window_id == window.id),
-    }) {
+    for window in snapshot
+        .windows
+        .iter()
+        .filter(|window| match register_name {
+            Some(register_name) => state
+                .registers
+                .iter()
+                .any(|register| register.name == register_name && register.window_id == window.id),
+            None => state
+                .registers
+                .iter()
+                .any(|register| register.window_id == window.id),
+        })
+    {
         let move_action = MoveWindowToWorkspace {
             window_id: Some(window.id),
             reference: niri_ipc::WorkspaceReferenceArg::Id(stash_workspace.id),
@@ -49,94 +52,73 @@ pub fn stash(socket: &mut Socket, state: &State, register_number: Option<i32>) {
     }
 }
 
+/// A register given by name, or one already looked up.
 pub enum RegisterInformation<'a> {
-    Id(i32),
+    /// A register's name, looked up in the state.
+    Name(&'a str),
+    /// A register taken from the state already.
     Register(&'a Register),
 }
 
+/// Moves a register's window to the workspace `destination` names, places it there as
+/// `placement` asks and focuses it.
 pub fn summon(
     socket: &mut Socket,
+    snapshot: &Snapshot,
     state: &State,
     register_info: RegisterInformation,
-) -> Result<()> {
-    let (focused_output, focused_window, workspaces) = match (
-        socket.send(Request::FocusedOutput)?,
-        socket.send(Request::FocusedWindow)?,
-        socket.send(Request::Workspaces)?,
-    ) {
-        (
-            Ok(Response::FocusedOutput(Some(focused_output))),
-            Ok(Response::FocusedWindow(focused_window)),
-            Ok(Response::Workspaces(workspaces)),
-        ) => (focused_output, focused_window, workspaces),
-        _ => return Ok(()),
-    };
+    destination: &Destination,
+    placement: &PlacementOptions,
+) {
     let found_register: &Register;
     match register_info {
-        RegisterInformation::Id(id) => {
-            if let Some(register) = state.get_register_ref_by_number(id) {
+        RegisterInformation::Name(name) => {
+            if let Some(register) = state.get_register_ref_by_name(name) {
                 found_register = register;
             } else {
-                return Ok(());
+                return;
             }
         }
         RegisterInformation::Register(register) => found_register = register,
     };
 
-    if let Some(focused_window) = focused_window {
-        if focused_window.id == found_register.window_id {
-            return Ok(());
-        }
-    };
-    let move_action = MoveWindowToMonitor {
-        id: Some(found_register.window_id),
-        output: focused_output.name,
-    };
-    let _ = socket.send(Request::Action(move_action));
-    let Some(focused_workspace) = workspaces.iter().find(|workspace| workspace.is_focused) else {
-        return Ok(());
+    let origin = state.stash_origins.get(&found_register.window_id).copied();
+    let Some(workspace) = snapshot.destination(destination, origin) else {
+        return;
     };
-    let move_action = MoveWindowToWorkspace {
-        window_id: Some(found_register.window_id),
-        reference: niri_ipc::WorkspaceReferenceArg::Id(focused_workspace.id),
-        focus: (true),
+    if snapshot.focused_window().is_some_and(|focused_window| {
+        focused_window.id == found_register.window_id
+            && focused_window.workspace_id == Some(workspace.id)
+    }) {
+        return;
     };
-    let _ = socket.send(Request::Action(move_action));
-    let focus_action = FocusWindow {
-        id: (found_register.window_id),
+    let Some(window) = snapshot.window(found_register.window_id) else {
+        return;
     };
-    let _ = socket.send(Request::Action(focus_action));
-    Ok(())
+    target_action::summon_window(socket, snapshot, window, workspace, placement);
 }
 
-pub fn check_status(socket: &mut Socket, register: &Register) -> RegisterStatus {
-    let Ok(Ok(Response::Windows(windows))) = socket.send(Request::Windows) else {
-        return RegisterStatus::WindowDropped;
-    };
-    match windows
-        .iter()
-        .find(|window| register.window_id == window.id)
-    {
+/// Looks up whether the register's window is still mapped.
+pub fn check_status(snapshot: &Snapshot, register: &Register) -> RegisterStatus {
+    match snapshot.window(register.window_id) {
         Some(_) => RegisterStatus::WindowMapped,
         None => RegisterStatus::WindowDropped,
     }
 }
 
+/// Compares registers with niri's windows, see [`State::syncronize_registers`].
 pub fn get_all_register_status(
-    socket: &mut Socket,
+    snapshot: &Snapshot,
     registers: Vec<&Register>,
-) -> Result<Vec<RegisterUpdate>> {
+) -> Vec<RegisterUpdate> {
     let mut register_state: Vec<RegisterUpdate> = Vec::new();
-    let Ok(Response::Windows(windows)) = socket.send(Request::Windows)? else {
-        return Ok(register_state); //return an empty map
-    };
     if let Some(orphaned_register) = registers
         .iter()
-        .find(|register| !windows.iter().any(|window| window.id == register.window_id))
+        .find(|register| snapshot.window(register.window_id).is_none())
     {
-        register_state.push(RegisterUpdate::Delete(orphaned_register.number))
+        register_state.push(RegisterUpdate::Delete(orphaned_register.name.clone()))
     };
-    for window in windows {
+    for window in &snapshot.windows {
         if let Some(register) = registers
             .iter()
             .find(|register| register.window_id == window.id)
@@ -145,9 +127,208 @@ pub fn get_all_register_status(
                 window_id: window.id,
                 title: window.title.clone(),
                 app_id: window.app_id.clone(),
-                ..**register
+                ..(*register).clone()
             }));
         };
     }
-    Ok(register_state)
+    register_state
+}
+
+/// Stashes the focused window into the register and puts the register's window where the focused
+/// window was: the same column and tile when tiled, the same position when floating, keeping the
+/// width either way. An empty or dropped register just takes the focused window.
+pub fn swap(socket: &mut Socket, snapshot: &Snapshot, state: &mut State, register_name: &str) {
+    let Some(stash_workspace) = snapshot.stash_workspace() else {
+        return;
+    };
+    let Some(focused_window) = snapshot.focused_window() else {
+        return;
+    };
+    let Some(workspace) = snapshot.workspace_of(focused_window) else {
+        return;
+    };
+    if workspace.id == stash_workspace.id {
+        return;
+    }
+    let register_window = state
+        .get_register_ref_by_name(register_name)
+        .and_then(|register| snapshot.window(register.window_id));
+    if register_window.is_some_and(|window| window.id == focused_window.id) {
+        return;
+    }
+
+    if let Some(register_window) = register_window {
+        if let Some(output) = &workspace.output {
+            let _ = socket.send(Request::Action(MoveWindowToMonitor {
+                id: Some(register_window.id),
+                output: output.clone(),
+            }));
+        }
+        let _ = socket.send(Request::Action(MoveWindowToWorkspace {
+            window_id: Some(register_window.id),
+            reference: niri_ipc::WorkspaceReferenceArg::Id(workspace.id),
+            focus: true,
+        }));
+        take_place_of(socket, &snapshot.windows, register_window, focused_window);
+    }
+
+    let _ = socket.send(Request::Action(MoveWindowToWorkspace {
+        window_id: Some(focused_window.id),
+        reference: niri_ipc::WorkspaceReferenceArg::Id(stash_workspace.id),
+        focus: false,
+    }));
+    if let Some(register_window) = register_window {
+        let _ = socket.send(Request::Action(FocusWindow {
+            id: register_window.id,
+        }));
+    }
+
+    let register = Register {
+        title: focused_window.title.clone(),
+        app_id: focused_window.app_id.clone(),
+        window_id: focused_window.id,
+        name: register_name.to_string(),
+        rule: None,
+    };
+    match state.get_register_ref_by_name(register_name) {
+        Some(_) => state.update_register(register),
+        None => state.registers.push(register),
+    }
+}
+
+/// Moves `window`, already on the workspace of `target`, into `target`'s column and tile or
+/// floating position. `target` is still there afterwards, right below or behind `window`.
+fn take_place_of(socket: &mut Socket, windows: &[Window], window: &Window, target: &Window) {
+    let width = SizeChange::SetFixed(target.layout.tile_size.0.round() as i32);
+
+    if target.is_floating {
+        if !window.is_floating {
+            set_floating(socket, window.id);
+        }
+        if let Some((x, y)) = target.layout.tile_pos_in_workspace_view {
+            let _ = socket.send(Request::Action(MoveFloatingWindow {
+                id: Some(window.id),
+                x: PositionChange::SetFixed(x),
+                y: PositionChange::SetFixed(y),
+            }));
+        }
+        let _ = socket.send(Request::Action(SetWindowWidth {
+            id: Some(window.id),
+            change: width,
+        }));
+        return;
+    }
+
+    let Some((column, tile)) = target.layout.pos_in_scrolling_layout else {
+        return;
+    };
+    if window.is_floating {
+        set_tiling(socket, window.id);
+    }
+    let _ = socket.send(Request::Action(FocusWindow { id: window.id }));
+    let _ = socket.send(Request::Action(MoveColumnToIndex { index: column + 1 }));
+
+    let tiles_in_column = windows
+        .iter()
+        .filter(|other| {
+            other.workspace_id == target.workspace_id
+                && other
+                    .layout
+                    .pos_in_scrolling_layout
+                    .is_some_and(|(other_column, _)| other_column == column)
+        })
+        .count();
+    if tiles_in_column > 1 {
+        // Consuming appends the window to the bottom of the column, walk it up to the target tile
+        let _ = socket.send(Request::Action(ConsumeOrExpelWindowLeft {
+            id: Some(window.id),
+        }));
+        for _ in tile..=tiles_in_column {
+            let _ = socket.send(Request::Action(MoveWindowUp {}));
+        }
+    } else {
+        let _ = socket.send(Request::Action(SetWindowWidth {
+            id: Some(window.id),
+            change: width,
+        }));
+    }
+}
+
+/// Moves every window on the stash workspace back to the workspace it was stashed from, or to the
+/// focused workspace when that is unknown or gone. Returns the windows that were moved.
+pub fn rescue(socket: &mut Socket, snapshot: &Snapshot, state: &mut State) -> Vec<Window> {
+    let (Some(stash_workspace), Some(focused_workspace)) =
+        (snapshot.stash_workspace(), snapshot.focused_workspace())
+    else {
+        return vec![];
+    };
+    let stranded: Vec<Window> = snapshot
+        .windows
+        .iter()
+        .filter(|window| snapshot.is_stashed(window))
+        .cloned()
+        .collect();
+    for window in &stranded {
+        let workspace_id = state
+            .stash_origins
+            .remove(&window.id)
+            .filter(|origin| *origin != stash_workspace.id && snapshot.workspace(*origin).is_some())
+            .unwrap_or(focused_workspace.id);
+        let _ = socket.send(Request::Action(MoveWindowToWorkspace {
+            window_id: Some(window.id),
+            reference: niri_ipc::WorkspaceReferenceArg::Id(workspace_id),
+            focus: false,
+        }));
+    }
+    stranded
+}
+
+/// Windows on the stash workspace that neither a register nor a known target holds, see
+/// [`State::is_tracked`].
+pub fn untracked_stashed(snapshot: &Snapshot, state: &State, targets: &[Target]) -> Vec<Window> {
+    snapshot
+        .windows
+        .iter()
+        .filter(|window| snapshot.is_stashed(window) && !state.is_tracked(window, targets))
+        .cloned()
+        .collect()
+}
+
+/// Binds every untracked window on the stash workspace, see [`untracked_stashed`] and
+/// [`adopt_window`]. Returns the names of the registers that were created.
+pub fn adopt_stashed(
+    snapshot: &Snapshot,
+    state: &mut State,
+    rules: &[Rule],
+    targets: &[Target],
+) -> Vec<String> {
+    untracked_stashed(snapshot, state, targets)
+        .into_iter()
+        .map(|window| adopt_window(state, &window, rules))
+        .collect()
+}
+
+/// Binds `window` to the register of the first rule that matches it while its register is unset,
+/// or else to the lowest numbered register that is unset. Returns the register's name.
+pub fn adopt_window(state: &mut State, window: &Window, rules: &[Rule]) -> String {
+    let (name, rule) = match rules.iter().find(|rule| {
+        rule.matches(window) && state.get_register_ref_by_name(&rule.register).is_none()
+    }) {
+        Some(rule) => (rule.register.clone(), Some(rule.name().to_string())),
+        None => (
+            (1..)
+                .map(|number: u32| number.to_string())
+                .find(|name| state.get_register_ref_by_name(name).is_none())
+                .unwrap_or_default(),
+            None,
+        ),
+    };
+    state.registers.push(Register {
+        title: window.title.clone(),
+        app_id: window.app_id.clone(),
+        window_id: window.id,
+        name: name.clone(),
+        rule,
+    });
+    name
 }
@@ -0,0 +1,121 @@
+//! Reloads the daemon's configuration when its file changes or the daemon gets `SIGHUP`. A config
+//! that fails to load is reported and the previous one stays in place, registers are not touched.
+
+use crate::client::Reply;
+use crate::config::Config;
+use inotify::{Inotify, WatchMask};
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use std::{
+    io::{Result, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+/// The daemon's configuration, swapped out whenever it is reloaded, and the `watch` clients that
+/// are told about reloads.
+#[derive(Debug, Clone)]
+pub struct SharedConfig {
+    path: Option<PathBuf>,
+    config: Arc<Mutex<Config>>,
+    watchers: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+impl SharedConfig {
+    /// `path` is the `--config` the daemon was started with.
+    pub fn new(config: Config, path: Option<PathBuf>) -> Self {
+        SharedConfig {
+            path,
+            config: Arc::new(Mutex::new(config)),
+            watchers: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// The configuration currently in effect.
+    pub fn get(&self) -> Config {
+        self.config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Keeps `stream` open to send it one [`Reply`] line per reload. Writes never block, a
+    /// watcher that stops reading is dropped once its socket buffer is full.
+    pub fn add_watcher(&self, stream: UnixStream) -> Result<()> {
+        stream.set_nonblocking(true)?;
+        self.watchers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(stream);
+        Ok(())
+    }
+
+    /// Loads the configuration again and swaps it in if it is valid.
+    pub fn reload(&self) {
+        let described_path = Config::path(self.path.as_deref())
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "the default configuration".to_string());
+        let reply: Reply = match Config::load(self.path.as_deref()) {
+            Ok(config) => {
+                *self.config.lock().unwrap_or_else(PoisonError::into_inner) = config;
+                eprintln!("Reloaded {described_path}");
+                Ok(format!("reloaded {described_path}"))
+            }
+            Err(error) => {
+                eprintln!(
+                    "Failed to reload {described_path}, keeping the previous config: {error}"
+                );
+                Err(format!("failed to reload: {error}"))
+            }
+        };
+        let Ok(line) = serde_json::to_string(&reply) else {
+            return;
+        };
+        let line = format!("{line}\n");
+        self.watchers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain_mut(|watcher| watcher.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+/// Reloads `config` on `SIGHUP` and whenever its file is written or replaced.
+pub fn spawn_config_watcher(config: SharedConfig) -> Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+    let on_hangup = config.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            on_hangup.reload();
+        }
+    });
+
+    let Some(file) = Config::path(config.path.as_deref()) else {
+        return Ok(());
+    };
+    // Editors tend to replace the file rather than write it, so the directory is watched
+    let (Some(directory), Some(file_name)) = (file.parent(), file.file_name()) else {
+        return Ok(());
+    };
+    if !directory.is_dir() {
+        return Ok(());
+    }
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        directory,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+    )?;
+    let file_name = file_name.to_owned();
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(events) = inotify.read_events_blocking(&mut buffer) {
+            if events
+                .into_iter()
+                .any(|event| event.name == Some(file_name.as_os_str()))
+            {
+                config.reload();
+            }
+        }
+    });
+    Ok(())
+}
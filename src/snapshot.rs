@@ -0,0 +1,118 @@
+//! What niri reports about windows and workspaces, queried once per command so every decision a
+//! command makes is based on the same consistent picture.
+
+use crate::args::Destination;
+use niri_ipc::{Request, Response, Window, Workspace, socket::Socket};
+use std::io::{Error, Result};
+
+/// Windows and workspaces at one point in time, along with the name of the stash workspace.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Every window niri reported.
+    pub windows: Vec<Window>,
+    /// Every workspace niri reported.
+    pub workspaces: Vec<Workspace>,
+    /// Name of the workspace that counts as the stash.
+    pub stash_name: String,
+}
+
+impl Snapshot {
+    /// Queries niri's windows and workspaces. `stash_name` is the workspace that counts as the
+    /// stash.
+    pub fn take(socket: &mut Socket, stash_name: &str) -> Result<Self> {
+        let (Ok(Response::Windows(windows)), Ok(Response::Workspaces(workspaces))) = (
+            socket.send(Request::Windows)?,
+            socket.send(Request::Workspaces)?,
+        ) else {
+            return Err(Error::other(
+                "niri did not report its windows and workspaces",
+            ));
+        };
+        Ok(Snapshot {
+            windows,
+            workspaces,
+            stash_name: stash_name.to_string(),
+        })
+    }
+
+    /// The window with id `window_id`.
+    pub fn window(&self, window_id: u64) -> Option<&Window> {
+        self.windows.iter().find(|window| window.id == window_id)
+    }
+
+    /// The workspace with id `workspace_id`.
+    pub fn workspace(&self, workspace_id: u64) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.id == workspace_id)
+    }
+
+    /// The focused window.
+    pub fn focused_window(&self) -> Option<&Window> {
+        self.windows.iter().find(|window| window.is_focused)
+    }
+
+    /// The focused workspace.
+    pub fn focused_workspace(&self) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.is_focused)
+    }
+
+    /// The workspace named [`Snapshot::stash_name`], if there is one.
+    pub fn stash_workspace(&self) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.name.as_deref() == Some(self.stash_name.as_str()))
+    }
+
+    /// The workspace `window` is on.
+    pub fn workspace_of(&self, window: &Window) -> Option<&Workspace> {
+        self.workspace(window.workspace_id?)
+    }
+
+    /// The workspace `destination` names, falling back to the focused workspace when there is no
+    /// such workspace or output. `origin` is where the window was stashed from, if known.
+    pub fn destination(
+        &self,
+        destination: &Destination,
+        origin: Option<u64>,
+    ) -> Option<&Workspace> {
+        let active_on = |output: &str| {
+            self.workspaces.iter().find(|workspace| {
+                workspace.is_active
+                    && workspace.output.as_deref() == Some(output)
+                    && workspace.name.as_deref() != Some(self.stash_name.as_str())
+            })
+        };
+        let workspace = match destination {
+            Destination::Focused => None,
+            Destination::Workspace(reference) => self
+                .workspaces
+                .iter()
+                .find(|workspace| workspace.name.as_deref() == Some(reference.as_str()))
+                .or_else(|| {
+                    let idx = reference.parse::<u8>().ok()?;
+                    let output = self.focused_workspace()?.output.as_deref();
+                    self.workspaces.iter().find(|workspace| {
+                        workspace.idx == idx && workspace.output.as_deref() == output
+                    })
+                }),
+            Destination::Output(output) => active_on(output),
+            Destination::Last => origin
+                .and_then(|origin| self.workspace(origin)?.output.as_deref())
+                .and_then(active_on),
+            Destination::StashOutput => self
+                .stash_workspace()
+                .and_then(|workspace| workspace.output.as_deref())
+                .and_then(active_on),
+        };
+        workspace.or_else(|| self.focused_workspace())
+    }
+
+    /// Whether `window` is on the stash workspace.
+    pub fn is_stashed(&self, window: &Window) -> bool {
+        self.stash_workspace()
+            .is_some_and(|stash_workspace| window.workspace_id == Some(stash_workspace.id))
+    }
+}
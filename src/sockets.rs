@@ -0,0 +1,203 @@
+//! Socket locations for the niri compositor and the register daemon, resolved once per process.
+
+use niri_ipc::socket::{SOCKET_PATH_ENV, Socket};
+use socket2::{Domain, SockAddr, Type};
+use std::{
+    env::var_os,
+    fs::{File, OpenOptions, Permissions, set_permissions},
+    io::{Error, ErrorKind, Result},
+    mem::size_of,
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::{
+            fs::{OpenOptionsExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+};
+
+/// The process on the other end of a Unix socket, as the kernel recorded it when it connected.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PeerCredentials {
+    pub pid: i32,
+    pub uid: u32,
+}
+
+/// Environment variable overriding the daemon socket path.
+pub const DAEMON_SOCKET_ENV: &str = "NIRI_SCRATCHPAD_SOCKET";
+
+/// Where the register daemon and niri listen.
+#[derive(Debug, Clone)]
+pub struct SocketPaths {
+    /// The register daemon's socket.
+    pub daemon: PathBuf,
+    /// niri's IPC socket.
+    pub niri: PathBuf,
+}
+
+impl SocketPaths {
+    /// Explicit paths win over the environment. Without either, the daemon socket is named after
+    /// the niri socket so every niri session gets a daemon of its own, unless only the systemd
+    /// socket exists.
+    pub fn resolve(daemon: Option<PathBuf>, niri: Option<PathBuf>) -> Result<Self> {
+        let niri = niri
+            .or_else(|| var_os(SOCKET_PATH_ENV).map(PathBuf::from))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("{SOCKET_PATH_ENV} not set and no --niri-socket given"),
+                )
+            })?;
+        let daemon = match daemon.or_else(|| var_os(DAEMON_SOCKET_ENV).map(PathBuf::from)) {
+            Some(daemon) => daemon,
+            None => default_daemon_socket(&niri)?,
+        };
+        Ok(SocketPaths { daemon, niri })
+    }
+
+    /// Opens a new IPC connection to niri.
+    pub fn connect_niri(&self) -> Result<Socket> {
+        Socket::connect_to(&self.niri)
+    }
+
+    /// Takes an exclusive lock on a file next to the daemon socket, waiting for another daemon
+    /// that holds it. The lock is released when the returned file is dropped.
+    pub(crate) fn lock_daemon(&self) -> Result<File> {
+        let mut path = self.daemon.clone().into_os_string();
+        path.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .mode(0o600)
+            .open(path)?;
+        // SAFETY: flock only takes the descriptor, which `file` keeps open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(file)
+    }
+
+    /// Listens on the daemon socket, readable and writable by the current user only.
+    pub(crate) fn bind_daemon(&self) -> Result<UnixListener> {
+        let socket = socket2::Socket::new(Domain::UNIX, Type::STREAM, None)?;
+        socket.bind(&SockAddr::unix(&self.daemon)?)?;
+        // Nobody can connect before `listen`, so the socket is private before it is reachable
+        set_permissions(&self.daemon, Permissions::from_mode(0o600))?;
+        socket.listen(128)?;
+        Ok(UnixListener::from(OwnedFd::from(socket)))
+    }
+}
+
+/// Who connected to `stream`, see `SO_PEERCRED` in socket(7).
+pub(crate) fn peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
+    let mut ucred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `ucred` and `len` outlive the call and `len` is the size of `ucred`
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        pid: ucred.pid,
+        uid: ucred.uid,
+    })
+}
+
+/// The real user id of this process.
+pub(crate) fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail
+    unsafe { libc::getuid() }
+}
+
+/// Socket the units in `contrib/systemd` listen on, relative to `XDG_RUNTIME_DIR`.
+const SYSTEMD_SOCKET: &str = "niri-scratchpad.sock";
+
+/// The socket of this niri session's daemon, or the socket activated one when only that exists.
+fn default_daemon_socket(niri: &Path) -> Result<PathBuf> {
+    let runtime_dir = var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "XDG_RUNTIME_DIR not set"))?;
+    Ok(daemon_socket_in(Path::new(&runtime_dir), niri))
+}
+
+fn daemon_socket_in(runtime_dir: &Path, niri: &Path) -> PathBuf {
+    // niri names its sockets `niri.<wayland display>.<pid>.sock`
+    let instance = niri
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.strip_prefix("niri.").unwrap_or(stem));
+    let file_name = match instance {
+        Some(instance) => format!("niri-register.{instance}.sock"),
+        None => "niri-register.sock".to_string(),
+    };
+    let session = runtime_dir.join(file_name);
+    let systemd = runtime_dir.join(SYSTEMD_SOCKET);
+    if !session.exists() && systemd.exists() {
+        return systemd;
+    }
+    session
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    fn runtime_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("niri-scratchpad-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn daemon_socket_is_named_after_the_niri_socket() {
+        let directory = runtime_dir("named");
+        assert_eq!(
+            daemon_socket_in(
+                &directory,
+                Path::new("/run/user/1000/niri.wayland-1.1234.sock")
+            ),
+            directory.join("niri-register.wayland-1.1234.sock")
+        );
+        assert_eq!(
+            daemon_socket_in(&directory, Path::new("/tmp/compositor.sock")),
+            directory.join("niri-register.compositor.sock")
+        );
+        assert_eq!(
+            daemon_socket_in(&directory, Path::new("/")),
+            directory.join("niri-register.sock")
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn daemon_socket_falls_back_to_the_systemd_socket() {
+        let directory = runtime_dir("systemd");
+        let niri = Path::new("niri.wayland-1.1234.sock");
+        fs::write(directory.join(SYSTEMD_SOCKET), "").unwrap();
+        assert_eq!(
+            daemon_socket_in(&directory, niri),
+            directory.join(SYSTEMD_SOCKET)
+        );
+
+        let session = directory.join("niri-register.wayland-1.1234.sock");
+        fs::write(&session, "").unwrap();
+        assert_eq!(daemon_socket_in(&directory, niri), session);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,102 +1,251 @@
-/// Register-based scratchpad utilize state to track the currently assigned window to that scratchpad register.
+//! Register-based scratchpad utilize state to track the currently assigned window to that scratchpad register.
+
+use crate::args::Property;
+use crate::config::Target;
+use crate::target_action::match_window_by_property;
+use crate::utils::is_descendant;
+use niri_ipc::Window;
 use serde::{Deserialize, Serialize};
-use std::{hash::Hash, io::Result};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    io::Result,
+    time::{Duration, Instant},
+};
 
+/// A window bound to a register, with the title and app id it had when last seen.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct Register {
+    /// The window's title when last seen.
     pub title: Option<String>,
+    /// The window's app id when last seen.
     pub app_id: Option<String>,
+    /// Id of the bound window.
     pub window_id: u64,
-    pub number: i32,
+    /// The register's name, what commands refer to it by.
+    pub name: String,
+    /// Name of the config rule that created this register, if any.
+    #[serde(default)]
+    pub rule: Option<String>,
+}
+
+/// A command spawned for an empty register, the first window it opens is bound to it.
+#[derive(Debug, Clone)]
+pub struct PendingSpawn {
+    /// The register the window is bound to.
+    pub register_name: String,
+    /// Set when the daemon launched the program itself rather than niri.
+    pub pid: Option<u32>,
+    /// The `--match` given along with `--spawn`, windows matching it count as the spawn's.
+    pub hint: Option<Property>,
+    /// Make the window floating once it is bound.
+    pub as_float: bool,
+    /// When the command was launched, see [`SPAWN_TIMEOUT`].
+    pub spawned_at: Instant,
 }
 
+/// A static target's spawn command that has not opened a matching window yet.
+#[derive(Debug, Clone)]
+pub struct PendingTarget {
+    /// The target whose window is awaited.
+    pub property: Property,
+    /// When the command was launched, see [`SPAWN_TIMEOUT`].
+    pub spawned_at: Instant,
+}
+
+/// Every register the daemon tracks.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct State {
+    /// Every register that is set.
     pub registers: Vec<Register>,
+    /// Spawns for empty registers that have not opened their window yet.
+    #[serde(skip)]
+    pub pending_spawns: Vec<PendingSpawn>,
+    /// Spawns for static targets that have not opened a matching window yet.
+    #[serde(skip)]
+    pub pending_targets: Vec<PendingTarget>,
+    /// Static targets the daemon has toggled, their windows belong in the stash.
+    #[serde(default)]
+    pub targets: Vec<Property>,
+    /// Workspace each stashed window was on before it went into the stash, by window id.
+    #[serde(default)]
+    pub stash_origins: HashMap<u64, u64>,
 }
 
-pub enum AddResult {
-    Added,
-    AlreadyExists(Register),
-}
+/// How long a spawned command gets to open its window before it counts as failed.
+pub const SPAWN_TIMEOUT: Duration = Duration::from_secs(20);
 
+/// Change to apply to [`State`] after comparing registers with niri's windows.
 pub enum RegisterUpdate {
-    Add(Register),
+    /// Replaces the register of the same name.
     Update(Register),
-    Delete(i32),
+    /// Deletes the register with this name.
+    Delete(String),
 }
 
 impl State {
+    /// A state without registers.
     pub fn new() -> Self {
-        State { registers: vec![] }
+        State {
+            registers: vec![],
+            pending_spawns: vec![],
+            pending_targets: vec![],
+            targets: vec![],
+            stash_origins: HashMap::new(),
+        }
     }
 
-    pub fn add_register(
+    /// Whether a spawn for the register is still waiting for its window.
+    pub fn is_spawn_pending(&self, register_name: &str) -> bool {
+        self.pending_spawns.iter().any(|pending| {
+            pending.register_name == register_name && pending.spawned_at.elapsed() < SPAWN_TIMEOUT
+        })
+    }
+
+    /// Remembers a static target the daemon toggled.
+    pub fn add_target(&mut self, property: Property) {
+        if !self.targets.contains(&property) {
+            self.targets.push(property);
+        }
+    }
+
+    /// Whether a register holds the window or a target matches it, one the daemon toggled or one
+    /// of the `configured` ones.
+    pub fn is_tracked(&self, window: &Window, configured: &[Target]) -> bool {
+        self.registers
+            .iter()
+            .any(|register| register.window_id == window.id)
+            || self
+                .targets
+                .iter()
+                .any(|property| match_window_by_property(window, property))
+            || configured
+                .iter()
+                .any(|target| match_window_by_property(window, &target.property()))
+    }
+
+    /// Waits for a window matching `property`, whose spawn command was just launched.
+    pub fn add_pending_target(&mut self, property: Property) {
+        self.pending_targets.push(PendingTarget {
+            property,
+            spawned_at: Instant::now(),
+        });
+    }
+
+    /// Whether the target's spawn command is still starting up.
+    pub fn is_target_pending(&self, property: &Property) -> bool {
+        self.pending_targets.iter().any(|pending| {
+            &pending.property == property && pending.spawned_at.elapsed() < SPAWN_TIMEOUT
+        })
+    }
+
+    /// Forgets pending targets the window matches, along with expired ones. Returns whether the
+    /// window belongs to one of them. Only targets spawned before the window was `first_seen`
+    /// count, an older window whose title changed to match was not opened by the spawn.
+    pub fn resolve_pending_targets(&mut self, window: &Window, first_seen: Instant) -> bool {
+        let pending_count = self.pending_targets.len();
+        self.pending_targets.retain(|pending| {
+            pending.spawned_at > first_seen || !match_window_by_property(window, &pending.property)
+        });
+        let resolved = self.pending_targets.len() < pending_count;
+        self.pending_targets
+            .retain(|pending| pending.spawned_at.elapsed() < SPAWN_TIMEOUT);
+        resolved
+    }
+
+    /// Waits for the window of a command just launched for `register_name`, see
+    /// [`PendingSpawn`].
+    pub fn add_pending_spawn(
         &mut self,
-        register_number: i32,
-        id: u64,
-        title: Option<String>,
-        app_id: Option<String>,
-    ) -> Result<()> {
-        self.registers.push(Register {
-            window_id: id,
-            number: register_number,
-            app_id,
-            title,
+        register_name: &str,
+        pid: Option<u32>,
+        hint: Option<Property>,
+        as_float: bool,
+    ) {
+        self.pending_spawns.push(PendingSpawn {
+            register_name: register_name.to_string(),
+            pid,
+            hint,
+            as_float,
+            spawned_at: Instant::now(),
         });
-        Ok(())
     }
 
-    pub fn delete_register(&mut self, register_number: i32) {
+    /// Removes and returns the spawn a new window belongs to: the one whose process or one of
+    /// its children opened it, or whose hint the window matches. Any other window is none of
+    /// their business, even while they wait.
+    pub fn take_pending_spawn(&mut self, window: &Window) -> Option<PendingSpawn> {
+        self.pending_spawns
+            .retain(|pending| pending.spawned_at.elapsed() < SPAWN_TIMEOUT);
+        let index = self.pending_spawns.iter().position(|pending| {
+            window
+                .pid
+                .and_then(|pid| u32::try_from(pid).ok())
+                .zip(pending.pid)
+                .is_some_and(|(pid, spawned)| is_descendant(pid, spawned))
+                || pending
+                    .hint
+                    .as_ref()
+                    .is_some_and(|hint| match_window_by_property(window, hint))
+        })?;
+        Some(self.pending_spawns.remove(index))
+    }
+
+    /// Unsets the register, if it is set.
+    pub fn delete_register(&mut self, register_name: &str) {
         self.registers
-            .retain(|register| register.number != register_number);
+            .retain(|register| register.name != register_name);
     }
 
-    pub fn get_register_by_number(&self, register_number: i32) -> Option<Register> {
+    /// A copy of the register named `register_name`.
+    pub fn get_register_by_name(&self, register_name: &str) -> Option<Register> {
         self.registers
             .iter()
-            .find(|register| register.number == register_number)
+            .find(|register| register.name == register_name)
             .cloned()
     }
 
-    pub fn get_register_ref_by_number(&self, register_number: i32) -> Option<&Register> {
+    /// The register named `register_name`.
+    pub fn get_register_ref_by_name(&self, register_name: &str) -> Option<&Register> {
         self.registers
             .iter()
-            .find(|register| register.number == register_number)
+            .find(|register| register.name == register_name)
     }
 
+    /// Every register that is set.
     pub fn get_tracked_registers(&self) -> Vec<&Register> {
         self.registers.iter().collect()
     }
 
+    /// Applies updates produced by [`crate::register_action::get_all_register_status`].
     pub fn syncronize_registers(&mut self, register_updates: Vec<RegisterUpdate>) -> Result<()> {
         for register_update in register_updates {
             match register_update {
-                RegisterUpdate::Add(register) => self.registers.push(register),
                 RegisterUpdate::Update(register) => {
                     if let Some(stored_register) = self
                         .registers
                         .iter_mut()
-                        .find(|found_register| found_register.number == register.number)
+                        .find(|found_register| found_register.name == register.name)
                     {
                         *stored_register = register;
                     }
                 }
-                RegisterUpdate::Delete(register_number) => self
+                RegisterUpdate::Delete(register_name) => self
                     .registers
-                    .retain(|stored_register| stored_register.number != register_number),
+                    .retain(|stored_register| stored_register.name != register_name),
             };
         }
         Ok(())
     }
 
+    /// Replaces the register of the same name, if it is set.
     pub fn update_register(&mut self, register_update: Register) {
         let Some(register) = self
             .registers
             .iter_mut()
-            .find(|register| register.number == register_update.number)
+            .find(|register| register.name == register_update.name)
         else {
-            return ();
+            return;
         };
         *register = register_update;
     }
@@ -107,3 +256,118 @@ impl Default for State {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::window;
+    use std::process::{self, Command};
+
+    fn app_id(value: &str) -> Property {
+        Property::AppId {
+            value: value.to_string(),
+        }
+    }
+
+    fn expired() -> Instant {
+        Instant::now() - SPAWN_TIMEOUT - Duration::from_secs(1)
+    }
+
+    #[test]
+    fn pending_target_resolves_with_a_window_first_seen_after_the_spawn() {
+        let mut state = State::new();
+        state.add_pending_target(app_id("foot"));
+        let other = window(2, "kitty", "htop", None);
+        assert!(!state.resolve_pending_targets(&other, Instant::now()));
+        assert!(state.is_target_pending(&app_id("foot")));
+        let foot = window(1, "foot", "htop", None);
+        assert!(state.resolve_pending_targets(&foot, Instant::now()));
+        assert!(!state.is_target_pending(&app_id("foot")));
+    }
+
+    #[test]
+    fn pending_target_ignores_windows_seen_before_the_spawn() {
+        let mut state = State::new();
+        let first_seen = Instant::now() - Duration::from_secs(1);
+        state.add_pending_target(app_id("foot"));
+        assert!(!state.resolve_pending_targets(&window(1, "foot", "htop", None), first_seen));
+        assert!(state.is_target_pending(&app_id("foot")));
+    }
+
+    #[test]
+    fn expired_pending_targets_are_forgotten() {
+        let mut state = State::new();
+        state.pending_targets.push(PendingTarget {
+            property: app_id("foot"),
+            spawned_at: expired(),
+        });
+        assert!(!state.is_target_pending(&app_id("foot")));
+        state.resolve_pending_targets(&window(1, "kitty", "htop", None), Instant::now());
+        assert!(state.pending_targets.is_empty());
+    }
+
+    #[test]
+    fn pending_spawn_takes_windows_of_the_spawned_process_and_its_children() {
+        let mut state = State::new();
+        state.add_pending_spawn("term", Some(process::id()), None, false);
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let child_window = window(1, "foot", "htop", Some(child.id() as i32));
+        let taken = state.take_pending_spawn(&child_window);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(
+            taken.map(|pending| pending.register_name).as_deref(),
+            Some("term")
+        );
+        assert!(state.pending_spawns.is_empty());
+    }
+
+    #[test]
+    fn pending_spawn_leaves_unrelated_windows_alone() {
+        let mut state = State::new();
+        state.add_pending_spawn("term", Some(process::id()), None, false);
+        state.add_pending_spawn("mail", None, Some(app_id("thunderbird")), false);
+        assert!(
+            state
+                .take_pending_spawn(&window(1, "foot", "htop", Some(1)))
+                .is_none()
+        );
+        assert!(
+            state
+                .take_pending_spawn(&window(2, "foot", "htop", None))
+                .is_none()
+        );
+        assert_eq!(state.pending_spawns.len(), 2);
+    }
+
+    #[test]
+    fn pending_spawn_takes_windows_matching_its_hint() {
+        let mut state = State::new();
+        state.add_pending_spawn("term", Some(process::id()), None, false);
+        state.add_pending_spawn("mail", None, Some(app_id("thunderbird")), true);
+        let taken = state
+            .take_pending_spawn(&window(1, "thunderbird", "Inbox", Some(1)))
+            .unwrap();
+        assert_eq!(taken.register_name, "mail");
+        assert!(taken.as_float);
+        assert_eq!(state.pending_spawns.len(), 1);
+    }
+
+    #[test]
+    fn expired_pending_spawns_take_nothing() {
+        let mut state = State::new();
+        state.pending_spawns.push(PendingSpawn {
+            register_name: "mail".to_string(),
+            pid: None,
+            hint: Some(app_id("thunderbird")),
+            as_float: false,
+            spawned_at: expired(),
+        });
+        assert!(
+            state
+                .take_pending_spawn(&window(1, "thunderbird", "Inbox", None))
+                .is_none()
+        );
+        assert!(state.pending_spawns.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Support for running the daemon as a systemd user service: taking over a listening socket
+//! passed through socket activation and reporting readiness for `Type=notify`.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString, c_int},
+    io::Result,
+    mem::size_of,
+    os::{
+        fd::FromRawFd,
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram, UnixListener},
+    },
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// First file descriptor systemd passes, see sd_listen_fds(3).
+const LISTEN_FDS_START: c_int = 3;
+
+/// Set once the passed descriptor is owned by a [`UnixListener`], so it is never closed twice.
+static LISTENER_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// What systemd passed to the daemon: the socket to listen on when it was socket activated, and
+/// where to report readiness when the service is `Type=notify`.
+#[derive(Debug, Default)]
+pub struct Activation {
+    /// The daemon socket, already listening, when the daemon was socket activated.
+    pub listener: Option<UnixListener>,
+    /// `$NOTIFY_SOCKET`, where to send `READY=1` once the daemon serves clients.
+    pub notify_socket: Option<OsString>,
+}
+
+impl Activation {
+    /// Takes the socket activation and notify variables out of the environment, so the programs
+    /// the daemon spawns do not see them, along with the listening socket they describe.
+    ///
+    /// # Safety
+    ///
+    /// Changes the environment, so no other thread may run yet, see [`env::remove_var`].
+    pub unsafe fn take() -> Self {
+        // SAFETY: the caller guarantees nothing else runs
+        unsafe {
+            Activation {
+                listener: inherited_listener(),
+                notify_socket: take_notify_socket(),
+            }
+        }
+    }
+}
+
+/// The listening socket systemd passed to this process, if it was socket activated.
+unsafe fn inherited_listener() -> Option<UnixListener> {
+    let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    // SAFETY: the caller guarantees nothing else runs
+    unsafe {
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+    }
+    if pid != std::process::id()
+        || fds < 1
+        || !is_unix_listener(LISTEN_FDS_START)
+        || LISTENER_TAKEN.swap(true, Ordering::SeqCst)
+    {
+        return None;
+    }
+    // SAFETY: systemd hands over the descriptor and it is a listening socket, nothing else in this
+    // process owns it
+    Some(unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// Whether `fd` is a listening Unix stream socket.
+fn is_unix_listener(fd: c_int) -> bool {
+    let option = |name| {
+        let mut value: c_int = 0;
+        let mut len = size_of::<c_int>() as libc::socklen_t;
+        // SAFETY: `value` and `len` outlive the call and `len` is the size of `value`, a
+        // descriptor that is no socket only makes the call fail
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                name,
+                (&raw mut value).cast(),
+                &mut len,
+            )
+        };
+        (result == 0).then_some(value)
+    };
+    option(libc::SO_DOMAIN) == Some(libc::AF_UNIX)
+        && option(libc::SO_TYPE) == Some(libc::SOCK_STREAM)
+        && option(libc::SO_ACCEPTCONN) == Some(1)
+}
+
+/// Takes `$NOTIFY_SOCKET` out of the environment, so the programs the daemon spawns cannot report
+/// to the service manager in its name.
+unsafe fn take_notify_socket() -> Option<OsString> {
+    let notify_socket = env::var_os("NOTIFY_SOCKET")?;
+    // SAFETY: the caller guarantees nothing else runs
+    unsafe { env::remove_var("NOTIFY_SOCKET") };
+    Some(notify_socket)
+}
+
+/// Tells the service manager listening on `notify_socket` that the daemon is ready.
+pub fn notify_ready(notify_socket: &OsStr) -> Result<()> {
+    let notify_socket = notify_socket.to_string_lossy().into_owned();
+    let address = match notify_socket.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&notify_socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(b"READY=1", &address)?;
+    Ok(())
+}
@@ -1,50 +1,55 @@
-use std::io::Result;
+//! Static scratchpads: every window matching a [`Property`] is toggled between the stash workspace
+//! and a destination, and launched when there is none.
 
-use niri_ipc::{Request, Response, Window, socket::Socket};
+use std::collections::HashMap;
+use std::io::{Error, Result};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
 
-use niri_ipc::Action::{FocusWindow, MoveWindowToMonitor, MoveWindowToWorkspace};
+use niri_ipc::{ColumnDisplay, Request, SizeChange, Window, Workspace, socket::Socket};
 
-use crate::args::Property;
+use niri_ipc::Action::{
+    ConsumeOrExpelWindowLeft, FocusWindow, MoveColumnToIndex, MoveColumnToLast,
+    MoveWindowToMonitor, MoveWindowToWorkspace, SetColumnDisplay, SetWindowWidth,
+};
+
+use crate::args::{
+    Destination, Elsewhere, Mode, Placement, PlacementOptions, Property, SpawnOptions,
+};
+use crate::config::Config;
+use crate::hooks::{HookContext, HookEvent, run_hook};
+use crate::snapshot::Snapshot;
+use crate::sockets::SocketPaths;
 use crate::target_action;
-use crate::utils::{set_floating, set_tiling};
+use crate::utils::{ensure_stash_workspace, set_floating, set_tiling};
 
+/// Windows matching a [`Property`], and whether any of them sits on the stash workspace.
 pub struct WindowTargetInformation {
+    /// Every matching window, in niri's order.
     pub windows: Vec<Window>,
+    /// Whether any of them is on the stash workspace.
     pub found_in_stash: bool,
 }
 
+/// Collects windows matching `property` from `snapshot`.
 pub fn get_windows_by_property(
-    socket: &mut Socket,
+    snapshot: &Snapshot,
     property: &Property,
-    workspace_id: u64,
 ) -> WindowTargetInformation {
-    let Ok(Ok(Response::Windows(windows))) = socket.send(Request::Windows) else {
-        return WindowTargetInformation {
-            windows: vec![],
-            found_in_stash: false,
-        };
-    };
-    let mut found_in_stash: bool = false;
-    let windows = windows
-        .into_iter()
-        .filter(|w| {
-            if match_window_by_property(w, property) {
-                if w.workspace_id.is_some_and(|wid| wid == workspace_id) {
-                    found_in_stash = true;
-                }
-                true
-            } else {
-                false
-            }
-        })
+    let windows: Vec<Window> = snapshot
+        .windows
+        .iter()
+        .filter(|window| match_window_by_property(window, property))
+        .cloned()
         .collect();
-
     WindowTargetInformation {
+        found_in_stash: windows.iter().any(|window| snapshot.is_stashed(window)),
         windows,
-        found_in_stash,
     }
 }
 
+/// Whether `window` matches `property`, exactly or through a title regex.
 pub fn match_window_by_property(window: &Window, property: &Property) -> bool {
     match property {
         Property::AppId { value } => window
@@ -55,9 +60,14 @@ pub fn match_window_by_property(window: &Window, property: &Property) -> bool {
             .title
             .as_deref()
             .is_some_and(|wtitle| wtitle == value),
+        Property::TitleRegex { value } => window
+            .title
+            .as_deref()
+            .is_some_and(|wtitle| value.is_match(wtitle)),
     }
 }
 
+/// Moves `window` to the stash workspace `workspace_id` without focusing it.
 pub fn stash_window(socket: &mut Socket, window: &Window, workspace_id: u64) {
     let _ = socket.send(Request::Action(niri_ipc::Action::MoveWindowToWorkspace {
         window_id: Some(window.id),
@@ -66,83 +76,342 @@ pub fn stash_window(socket: &mut Socket, window: &Window, workspace_id: u64) {
     }));
 }
 
-pub fn summon_window(socket: &mut Socket, window: &Window, workspace_id: u64) -> Result<()> {
-    let Ok(Response::FocusedOutput(Some(output))) = socket.send(Request::FocusedOutput)? else {
-        return Ok(());
-    };
-
-    let move_action = MoveWindowToMonitor {
-        id: Some(window.id),
-        output: output.name,
-    };
-    let _ = socket.send(Request::Action(move_action));
+/// Moves `window` to `workspace` and its output, then focuses it. A tiled window is then placed
+/// next to the column that was focused on `workspace` in `snapshot`, see [`PlacementOptions`].
+pub fn summon_window(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    window: &Window,
+    workspace: &Workspace,
+    placement: &PlacementOptions,
+) {
+    if let Some(output) = &workspace.output {
+        let move_action = MoveWindowToMonitor {
+            id: Some(window.id),
+            output: output.clone(),
+        };
+        let _ = socket.send(Request::Action(move_action));
+    }
     let move_action = MoveWindowToWorkspace {
         window_id: Some(window.id),
-        reference: niri_ipc::WorkspaceReferenceArg::Id(workspace_id),
+        reference: niri_ipc::WorkspaceReferenceArg::Id(workspace.id),
         focus: (true),
     };
     let _ = socket.send(Request::Action(move_action));
     let focus_action = FocusWindow { id: (window.id) };
     let _ = socket.send(Request::Action(focus_action));
-    Ok(())
+    if !window.is_floating {
+        place_window(socket, snapshot, window, workspace, placement);
+    }
+}
+
+/// Moves the focused column, which holds the summoned `window`, to where `placement` asks and sets
+/// its width. Placements relative to the focused column do nothing on an empty workspace.
+fn place_window(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
+    window: &Window,
+    workspace: &Workspace,
+    placement: &PlacementOptions,
+) {
+    // The window niri focuses on the workspace, its column is where we place ours
+    let focused_tile = workspace
+        .active_window_id
+        .filter(|id| *id != window.id)
+        .and_then(|id| snapshot.window(id))
+        .and_then(|focused| Some((focused.layout.pos_in_scrolling_layout?, focused)));
+    let mut width = placement.width;
+    let mut actions = Vec::new();
+    match (placement.placement, focused_tile) {
+        (Placement::Auto, _) => {}
+        (Placement::End, _) => actions.push(MoveColumnToLast {}),
+        (Placement::Right, Some(((column, _), _))) => {
+            actions.push(MoveColumnToIndex { index: column + 1 });
+        }
+        (Placement::Tab | Placement::Stack, Some(((column, _), _))) => {
+            actions.push(MoveColumnToIndex { index: column + 1 });
+            actions.push(ConsumeOrExpelWindowLeft {
+                id: Some(window.id),
+            });
+            let display = match placement.placement {
+                Placement::Tab => ColumnDisplay::Tabbed,
+                _ => ColumnDisplay::Normal,
+            };
+            actions.push(SetColumnDisplay { display });
+        }
+        (Placement::Replace, Some(((column, _), focused))) => {
+            actions.push(MoveColumnToIndex { index: column });
+            width = width.or(Some(SizeChange::SetFixed(
+                focused.layout.tile_size.0.round() as i32,
+            )));
+        }
+        (Placement::Right | Placement::Tab | Placement::Stack | Placement::Replace, None) => {}
+    }
+    if let Some(change) = width {
+        actions.push(SetWindowWidth {
+            id: Some(window.id),
+            change,
+        });
+    }
+    for action in actions {
+        let _ = socket.send(Request::Action(action));
+    }
+}
+
+/// What [`handle_target`] did with the matching windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetOutcome {
+    /// A matching window was focused where it is.
+    Focused,
+    /// The matching windows were moved to the destination.
+    Summoned,
+    /// The matching windows were moved to the stash workspace.
+    Stashed,
+    /// Nothing matched, so the spawn command was launched.
+    Spawned,
+    /// Nothing needed doing, or there is no stash or destination workspace.
+    Nothing,
+}
+
+/// How a toggle moves windows, shared by targets and registers.
+#[derive(Debug, Clone)]
+pub struct ToggleOptions {
+    /// Restricts the toggle to one direction.
+    pub mode: Mode,
+    /// Focus a visible but unfocused window before anything is stashed.
+    pub focus_first: bool,
+    /// Make summoned windows floating.
+    pub as_float: bool,
+    /// Make floating windows tiled before they are stashed, so niri animates the move.
+    pub animations: bool,
+    /// What to do with windows on another workspace, see [`Elsewhere`].
+    pub elsewhere: Elsewhere,
+    /// Where summoned windows go, which also counts as here for the toggle.
+    pub destination: Destination,
+    /// Where summoned tiled windows land on their workspace.
+    pub placement: PlacementOptions,
 }
 
+/// Toggles every window matching `property` between the stash workspace and the destination
+/// workspace, spawning `spawn` when nothing matches. `stash_origins` are the workspaces windows
+/// were stashed from, see [`crate::state::State::stash_origins`].
 pub fn handle_target(
+    socket: &mut Socket,
+    snapshot: &Snapshot,
     property: Property,
-    spawn: Option<String>,
-    as_float: bool,
-    animations: bool,
-) -> Result<()> {
-    let mut socket = Socket::connect()?;
-
-    let Ok(Response::Workspaces(workspaces)) = socket.send(Request::Workspaces)? else {
-        return Ok(());
-    };
-
-    let Some(current_workspace) = workspaces.iter().find(|workspace| workspace.is_focused) else {
-        return Ok(());
+    spawn: Option<SpawnOptions>,
+    options: ToggleOptions,
+    stash_origins: &HashMap<u64, u64>,
+) -> Result<TargetOutcome> {
+    let ToggleOptions {
+        mode,
+        focus_first,
+        as_float,
+        animations,
+        elsewhere,
+        destination,
+        placement,
+    } = options;
+    let Some(current_workspace) = snapshot.destination(&destination, None) else {
+        return Ok(TargetOutcome::Nothing);
     };
-
-    let Some(stash_workspace) = workspaces
-        .iter()
-        .find(|workspace| Some("stash") == workspace.name.as_deref())
-    else {
-        return Ok(());
+    let Some(stash_workspace) = snapshot.stash_workspace() else {
+        return Ok(TargetOutcome::Nothing);
     };
-    let window_target_information =
-        get_windows_by_property(&mut socket, &property, stash_workspace.id);
+    let window_target_information = get_windows_by_property(snapshot, &property);
 
-    if let Some(command) = spawn
+    if let Some(spawn) = spawn
+        && mode != Mode::Hide
         && window_target_information.windows.is_empty()
     {
-        target_action::spawn(&mut socket, command);
-        return Ok(());
+        target_action::spawn(socket, &spawn, (TARGET_ENV, property.to_string()))?;
+        return Ok(TargetOutcome::Spawned);
     };
 
-    if !window_target_information.windows.is_empty() {
-        // tl;dr if there are ny matching windows found in the stash workspace, we simply move
-        // everything up to the focused workspace, regardless if there are matched windows in current workspace
-        // otherwise we'll be playing switcheroo if matched windows exist in stash and focused simultaneously
-        if window_target_information.found_in_stash {
-            for window in window_target_information.windows {
-                target_action::summon_window(&mut socket, &window, current_workspace.id)?;
-                if as_float {
-                    set_floating(&mut socket, window.id);
-                }
+    if window_target_information.windows.is_empty() {
+        return Ok(TargetOutcome::Nothing);
+    }
+    // Windows the user can't see here and that aren't stashed either go by `elsewhere`
+    let none_here = !window_target_information.found_in_stash
+        && window_target_information
+            .windows
+            .iter()
+            .all(|window| window.workspace_id != Some(current_workspace.id));
+    if none_here
+        && mode != Mode::Hide
+        && elsewhere == Elsewhere::Goto
+        && let Some(window) = window_target_information.windows.first()
+    {
+        let _ = socket.send(Request::Action(FocusWindow { id: window.id }));
+        return Ok(TargetOutcome::Focused);
+    }
+    // tl;dr if there are ny matching windows found in the stash workspace, we simply move
+    // everything up to the focused workspace, regardless if there are matched windows in current workspace
+    // otherwise we'll be playing switcheroo if matched windows exist in stash and focused simultaneously
+    let summon = match mode {
+        Mode::Toggle | Mode::Show => {
+            window_target_information.found_in_stash || none_here && elsewhere == Elsewhere::Bring
+        }
+        Mode::Hide => false,
+    };
+    if focus_first
+        && mode != Mode::Hide
+        && !summon
+        && !window_target_information
+            .windows
+            .iter()
+            .any(|window| window.is_focused)
+        && let Some(window) = window_target_information
+            .windows
+            .iter()
+            .find(|window| window.workspace_id == Some(current_workspace.id))
+    {
+        let _ = socket.send(Request::Action(FocusWindow { id: window.id }));
+        return Ok(TargetOutcome::Focused);
+    }
+    if mode == Mode::Show && !summon
+        || mode == Mode::Hide
+            && window_target_information
+                .windows
+                .iter()
+                .all(|window| window.workspace_id == Some(stash_workspace.id))
+    {
+        return Ok(TargetOutcome::Nothing);
+    }
+    if summon {
+        for window in window_target_information.windows {
+            let origin = stash_origins.get(&window.id).copied();
+            let workspace = snapshot
+                .destination(&destination, origin)
+                .unwrap_or(current_workspace);
+            if as_float {
+                target_action::summon_window(
+                    socket,
+                    snapshot,
+                    &window,
+                    workspace,
+                    &PlacementOptions::default(),
+                );
+                set_floating(socket, window.id);
+            } else {
+                target_action::summon_window(socket, snapshot, &window, workspace, &placement);
             }
-        } else {
-            for window in window_target_information.windows {
-                if animations && window.is_floating {
-                    set_tiling(&mut socket, window.id);
-                }
-                target_action::stash_window(&mut socket, &window, stash_workspace.id);
+        }
+        Ok(TargetOutcome::Summoned)
+    } else {
+        for window in window_target_information
+            .windows
+            .into_iter()
+            .filter(|window| window.workspace_id != Some(stash_workspace.id))
+        {
+            if animations && window.is_floating {
+                set_tiling(socket, window.id);
             }
+            target_action::stash_window(socket, &window, stash_workspace.id);
         }
+        Ok(TargetOutcome::Stashed)
     }
+}
 
-    return Ok(());
+/// Toggles a static target like [`handle_target`] without a daemon: connects to niri through
+/// `paths`, names a stash workspace when `config` allows it and waits for the spawn hook, since
+/// nothing outlives the process to run it. Returns a message for the user when a stash workspace
+/// was named or is missing.
+pub fn handle_target_once(
+    paths: &SocketPaths,
+    config: &Config,
+    property: &Property,
+    spawn: Option<SpawnOptions>,
+    options: ToggleOptions,
+) -> Result<Option<String>> {
+    let mut socket = paths.connect_niri()?;
+    let mut snapshot = Snapshot::take(&mut socket, &config.stash.workspace)?;
+    let (_, notice) = ensure_stash_workspace(&mut socket, &mut snapshot, &config.stash);
+    let outcome = handle_target(
+        &mut socket,
+        &snapshot,
+        property.clone(),
+        spawn,
+        options,
+        &HashMap::new(),
+    )?;
+    if outcome == TargetOutcome::Spawned
+        && let Some(hook) = run_hook(
+            &config.hooks,
+            HookEvent::Spawn,
+            HookContext::target(property),
+        )
+    {
+        let _ = hook.join();
+    }
+    Ok(notice)
 }
 
-pub fn spawn(socket: &mut Socket, command: String) {
-    let _ = socket.send(Request::Action(niri_ipc::Action::SpawnSh { command }));
+/// Environment variable telling a spawned program which static target it was launched for.
+pub const TARGET_ENV: &str = "NIRI_SCRATCHPAD_TARGET";
+/// Environment variable telling a spawned program which register it was launched for.
+pub const REGISTER_ENV: &str = "NIRI_SCRATCHPAD_REGISTER";
+
+/// Launches the application through niri, so it gets niri's environment, and returns `None`.
+/// With a working directory or extra environment it is launched by [`spawn_process`] instead,
+/// which returns its pid. `scratchpad_env` is one of [`TARGET_ENV`] or [`REGISTER_ENV`] with its
+/// value, `env` passes it on either way.
+pub fn spawn(
+    socket: &mut Socket,
+    spawn: &SpawnOptions,
+    scratchpad_env: (&str, String),
+) -> Result<Option<u32>> {
+    if spawn.cwd.is_some() || !spawn.env.is_empty() {
+        return spawn_process(spawn, scratchpad_env).map(Some);
+    }
+    let mut command = vec![
+        "env".to_string(),
+        format!("{}={}", scratchpad_env.0, scratchpad_env.1),
+    ];
+    if spawn.spawn_argv.is_empty() {
+        command.extend([
+            "sh".to_string(),
+            "-c".to_string(),
+            spawn.spawn.clone().unwrap_or_default(),
+        ]);
+    } else {
+        command.extend(spawn.spawn_argv.iter().cloned());
+    }
+    socket
+        .send(Request::Action(niri_ipc::Action::Spawn { command }))?
+        .map_err(Error::other)?;
+    Ok(None)
+}
+
+/// Launches the application as a process group of its own and returns its pid. `scratchpad_env`
+/// is one of [`TARGET_ENV`] or [`REGISTER_ENV`] with its value.
+pub fn spawn_process(spawn: &SpawnOptions, scratchpad_env: (&str, String)) -> Result<u32> {
+    let mut command = match spawn.spawn_argv.split_first() {
+        Some((program, args)) => {
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+        None => {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(spawn.spawn.as_deref().unwrap_or_default());
+            command
+        }
+    };
+    if let Some(cwd) = &spawn.cwd {
+        command.current_dir(cwd);
+    }
+    let mut child = command
+        .envs(spawn.env.iter().map(|(key, value)| (key, value)))
+        .env(scratchpad_env.0, scratchpad_env.1)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    let pid = child.id();
+    // Reap the child once it exits, the daemon would otherwise collect zombies
+    thread::spawn(move || child.wait());
+    Ok(pid)
 }
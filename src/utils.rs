@@ -1,5 +1,12 @@
-use niri_ipc::{Action::MoveWindowToFloating, Action::MoveWindowToTiling, Request, socket::Socket};
+use crate::config::Stash;
+use crate::snapshot::Snapshot;
+use niri_ipc::{
+    Action::{MoveWindowToFloating, MoveWindowToTiling, SetWorkspaceName},
+    Request, WorkspaceReferenceArg,
+    socket::Socket,
+};
 
+/// Moves the window to the floating layout.
 pub fn set_floating(socket: &mut Socket, window_id: u64) {
     let floating_action = MoveWindowToFloating {
         id: (Some(window_id)),
@@ -7,9 +14,153 @@ pub fn set_floating(socket: &mut Socket, window_id: u64) {
     socket.send(Request::Action(floating_action)).ok();
 }
 
+/// Moves the window to the tiling layout.
 pub fn set_tiling(socket: &mut Socket, window_id: u64) {
     let tiling_action = MoveWindowToTiling {
         id: (Some(window_id)),
     };
     socket.send(Request::Action(tiling_action)).ok();
 }
+
+/// Returns the id of the stash workspace. When there is none and `stash.create` is set, the last
+/// empty workspace on the stash output is named after it, in `snapshot` as well. The message is
+/// for the user and says which workspace was named, or why there is no stash workspace.
+pub fn ensure_stash_workspace(
+    socket: &mut Socket,
+    snapshot: &mut Snapshot,
+    stash: &Stash,
+) -> (Option<u64>, Option<String>) {
+    if let Some(workspace) = snapshot.stash_workspace() {
+        return (Some(workspace.id), None);
+    }
+    let missing = format!("No workspace named `{}`", snapshot.stash_name);
+    if !stash.create {
+        return (None, Some(missing));
+    }
+    let Some(output) = stash.output.clone().or_else(|| {
+        snapshot
+            .focused_workspace()
+            .and_then(|workspace| workspace.output.clone())
+    }) else {
+        return (
+            None,
+            Some(format!("{missing} and no output to name one on")),
+        );
+    };
+    // niri keeps an empty workspace at the end of every output, it is never the active one unless
+    // the user is looking at it
+    let Some(workspace) = snapshot
+        .workspaces
+        .iter_mut()
+        .filter(|workspace| {
+            workspace.output.as_ref() == Some(&output)
+                && workspace.name.is_none()
+                && workspace.active_window_id.is_none()
+                && !workspace.is_active
+        })
+        .max_by_key(|workspace| workspace.idx)
+    else {
+        return (
+            None,
+            Some(format!(
+                "{missing} and no empty workspace on {output} to name so"
+            )),
+        );
+    };
+    let _ = socket.send(Request::Action(SetWorkspaceName {
+        name: snapshot.stash_name.clone(),
+        workspace: Some(WorkspaceReferenceArg::Id(workspace.id)),
+    }));
+    workspace.name = Some(snapshot.stash_name.clone());
+    let named = format!(
+        "Named workspace {} on {output} `{}` to stash windows on",
+        workspace.idx, snapshot.stash_name
+    );
+    (Some(workspace.id), Some(named))
+}
+
+/// Whether process `pid` is `ancestor` or was started by it, following parents through `/proc`.
+pub fn is_descendant(pid: u32, ancestor: u32) -> bool {
+    let mut pid = pid;
+    // Every chain ends at init, or at 0 for processes of the kernel
+    while pid > 1 {
+        if pid == ancestor {
+            return true;
+        }
+        let Some(parent) = parent_pid(pid) else {
+            return false;
+        };
+        pid = parent;
+    }
+    false
+}
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    stat_parent(&std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+}
+
+/// The parent pid in the contents of a `/proc/<pid>/stat` file.
+fn stat_parent(stat: &str) -> Option<u32> {
+    // The command name may hold spaces and parentheses, the state and parent come after its last `)`
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use niri_ipc::{Window, WindowLayout};
+    use std::process::{self, Command};
+
+    /// A tiled window on workspace 1.
+    pub(crate) fn window(id: u64, app_id: &str, title: &str, pid: Option<i32>) -> Window {
+        Window {
+            id,
+            title: Some(title.to_string()),
+            app_id: Some(app_id.to_string()),
+            pid,
+            workspace_id: Some(1),
+            is_focused: false,
+            is_floating: false,
+            is_urgent: false,
+            layout: WindowLayout {
+                pos_in_scrolling_layout: Some((1, 1)),
+                tile_size: (800.0, 600.0),
+                window_size: (800, 600),
+                tile_pos_in_workspace_view: None,
+                window_offset_in_tile: (0.0, 0.0),
+            },
+        }
+    }
+
+    #[test]
+    fn stat_parent_skips_command_names_with_spaces_and_parentheses() {
+        assert_eq!(stat_parent("4242 (foot) S 17 4242 4242 0 -1"), Some(17));
+        assert_eq!(stat_parent("4242 (my (odd) app) R 99 4242 0"), Some(99));
+        assert_eq!(stat_parent("4242 (a) b) S 5 4242"), Some(5));
+        assert_eq!(stat_parent("4242 no command name"), None);
+        assert_eq!(stat_parent("4242 (foot) S"), None);
+    }
+
+    #[test]
+    fn children_descend_from_this_process() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let descends = is_descendant(child.id(), process::id());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(descends);
+        assert!(is_descendant(process::id(), process::id()));
+    }
+
+    #[test]
+    fn ancestors_and_unknown_processes_do_not_descend() {
+        let parent = parent_pid(process::id()).unwrap();
+        assert!(!is_descendant(parent, process::id()));
+        assert!(!is_descendant(1, process::id()));
+        assert!(!is_descendant(u32::MAX, process::id()));
+    }
+}